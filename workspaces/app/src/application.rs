@@ -1,4 +1,5 @@
 mod error_dialog;
+mod icons;
 mod pages;
 mod window;
 
@@ -12,10 +13,10 @@ use common::{
     utils,
 };
 use error_dialog::ErrorDialog;
-use gtk::{IconTheme, Image, gdk};
+use gtk::{IconTheme, Image, gdk, glib};
 use pages::{Page, Pages};
 use std::{cell::RefCell, path::Path, rc::Rc};
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 use window::AppWindow;
 
 pub struct App {
@@ -37,9 +38,9 @@ impl App {
             ));
             let app_dirs = AppDirs::new();
             let window = AppWindow::new(adw_application);
-            let fetch = Fetch::new();
+            let fetch = Fetch::new(&app_dirs);
             let pages = Pages::new();
-            let browsers = BrowserConfigs::new(&icon_theme, &app_dirs);
+            let browsers = BrowserConfigs::new(&app_dirs);
             let error_dialog = ErrorDialog::new();
 
             Self {
@@ -64,8 +65,9 @@ impl App {
 
             self.dirs.init()?;
             assets::init(&self.dirs)?;
+            self.fetch.init();
             self.add_system_icon_paths();
-            self.browser_configs.init();
+            self.browser_configs.load();
 
             // Last
             self.pages.init(self);
@@ -76,6 +78,8 @@ impl App {
                 self.navigate(&Page::Home);
             }
 
+            self.detect_browsers();
+
             Ok(())
         })() {
             self.show_error(&error);
@@ -121,6 +125,30 @@ impl App {
         self.window.view.on_app_update();
     }
 
+    /// After a browser config changed, the pages fill up again while detecting
+    pub fn reload_browsers(self: &Rc<Self>) {
+        info!("Reloading browser configs");
+        self.browser_configs.load();
+        self.on_browsers_change();
+        self.detect_browsers();
+    }
+
+    fn detect_browsers(self: &Rc<Self>) {
+        let self_clone = self.clone();
+        glib::spawn_future_local(async move {
+            let app = self_clone.clone();
+            self_clone
+                .browser_configs
+                .detect(move || app.on_browsers_change())
+                .await;
+        });
+    }
+
+    fn on_browsers_change(self: &Rc<Self>) {
+        self.add_browser_icon_paths();
+        self.pages.on_browsers_change(self);
+    }
+
     fn add_system_icon_paths(self: &Rc<Self>) {
         if utils::env::is_flatpak_container() {
             for path in self.dirs.system_icons() {
@@ -128,4 +156,12 @@ impl App {
             }
         }
     }
+
+    fn add_browser_icon_paths(self: &Rc<Self>) {
+        for browser in self.browser_configs.get_all_browsers() {
+            if let Some(path) = &browser.icon_search_path {
+                self.add_icon_search_path(path);
+            }
+        }
+    }
 }
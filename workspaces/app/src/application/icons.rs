@@ -0,0 +1,85 @@
+use common::{browsers::Browser, desktop_file::DesktopFile};
+use gtk::{
+    IconLookupFlags, IconTheme, Image, TextDirection, gdk,
+    gdk_pixbuf::Pixbuf,
+    prelude::{FileExt, WidgetExt},
+};
+
+pub trait BrowserIcon {
+    fn get_icon(&self) -> Image;
+    fn get_icon_pixbuf(&self, size: i32) -> Option<Pixbuf>;
+}
+impl BrowserIcon for Browser {
+    fn get_icon(&self) -> Image {
+        const FALLBACK_IMAGE: &str = "web-browser-symbolic";
+
+        let Some(display) = gdk::Display::default() else {
+            return Image::from_icon_name(FALLBACK_IMAGE);
+        };
+        let icon_theme = IconTheme::for_display(&display);
+
+        if let Some(icon_file) = &self.icon_file {
+            return Image::from_file(icon_file);
+        }
+
+        for icon in &self.icon_names {
+            if !icon_theme.has_icon(icon) {
+                continue;
+            }
+            let image = Image::from_icon_name(icon);
+            if image.uses_fallback() {
+                continue;
+            }
+            return image;
+        }
+
+        Image::from_icon_name(FALLBACK_IMAGE)
+    }
+
+    fn get_icon_pixbuf(&self, size: i32) -> Option<Pixbuf> {
+        if let Some(icon_file) = &self.icon_file {
+            return Pixbuf::from_file_at_size(icon_file, size, size).ok();
+        }
+
+        let display = gdk::Display::default()?;
+        let icon_theme = IconTheme::for_display(&display);
+
+        let icon = self
+            .icon_names
+            .iter()
+            .find(|icon| icon_theme.has_icon(icon))?;
+        let path = icon_theme
+            .lookup_icon(
+                icon,
+                &[],
+                size,
+                1,
+                TextDirection::None,
+                IconLookupFlags::empty(),
+            )
+            .file()?
+            .path()?;
+
+        Pixbuf::from_file_at_size(path, size, size).ok()
+    }
+}
+
+pub trait DesktopFileIcon {
+    fn get_icon(&self) -> Image;
+}
+impl DesktopFileIcon for DesktopFile {
+    fn get_icon(&self) -> Image {
+        let fallback_icon = "image-missing-symbolic";
+        let icon_path = self.get_icon_path().unwrap_or_default();
+        // Read the installed file, the icon theme may not have seen it yet
+        if let Some(icon_file) = self.get_icon_file() {
+            Image::from_file(icon_file)
+        } else if !icon_path.as_os_str().is_empty() {
+            Image::from_icon_name(&icon_path.to_string_lossy())
+        } else {
+            let image = Image::from_icon_name(fallback_icon);
+            image.add_css_class("error");
+            image
+        }
+    }
+}
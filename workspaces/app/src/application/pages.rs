@@ -55,6 +55,12 @@ impl Pages {
         sidebar.add_nav_row(app.clone(), Page::Info);
     }
 
+    /// Browsers were found or the configs were reloaded
+    pub fn on_browsers_change(&self, app: &Rc<App>) {
+        self.web_apps.on_browsers_change(app);
+        self.browsers.reload_browser_sections(app);
+    }
+
     pub fn get(&self, page: &Page) -> Rc<dyn NavPage> {
         match page {
             Page::Home => self.home.clone(),
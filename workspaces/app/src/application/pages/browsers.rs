@@ -1,21 +1,27 @@
+mod browser_editor;
+
 use super::NavPage;
-use crate::application::{App, pages::PrefPage};
-use common::browsers::{Base, Browser};
+use crate::application::{App, icons::BrowserIcon, pages::PrefPage};
+use browser_editor::BrowserEditor;
+use common::browsers::{Base, Browser, Installation};
 use gtk::{
-    Align, Label, Orientation,
-    prelude::{BoxExt, WidgetExt},
+    Align, Button, Image, Label, Orientation,
+    prelude::{BoxExt, ButtonExt, WidgetExt},
 };
 use libadwaita::{
-    ActionRow, ExpanderRow, NavigationPage, PreferencesGroup, PreferencesPage, StatusPage,
-    prelude::{ExpanderRowExt, PreferencesGroupExt, PreferencesPageExt},
+    ActionRow, ButtonContent, ExpanderRow, NavigationPage, PreferencesGroup, PreferencesPage,
+    Spinner, StatusPage, Toast, ToastOverlay,
+    prelude::{ActionRowExt, ExpanderRowExt, PreferencesGroupExt, PreferencesPageExt},
 };
 use std::fmt::Write as _;
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 pub struct BrowsersPage {
     nav_page: NavigationPage,
     nav_row: ActionRow,
     prefs_page: PreferencesPage,
+    toast_overlay: ToastOverlay,
+    browser_sections: RefCell<Vec<PreferencesGroup>>,
 }
 impl NavPage for BrowsersPage {
     fn get_navpage(&self) -> &NavigationPage {
@@ -35,6 +41,7 @@ impl BrowsersPage {
             nav_page,
             nav_row,
             prefs_page,
+            toast_overlay,
             ..
         } = Self::build_nav_page(title, icon).with_preference_page();
 
@@ -42,23 +49,83 @@ impl BrowsersPage {
             nav_page,
             nav_row,
             prefs_page,
+            toast_overlay,
+            browser_sections: RefCell::new(Vec::new()),
         })
     }
 
     pub fn init(self: &Rc<Self>, app: &Rc<App>) {
-        let browser_pref_groups = Self::build_browser_sections(app);
+        self.prefs_page.add(&self.build_editor_section(app));
+        self.reload_browser_sections(app);
+    }
+
+    pub fn reload_browser_sections(self: &Rc<Self>, app: &Rc<App>) {
+        for pref_group in self.browser_sections.borrow_mut().drain(..) {
+            self.prefs_page.remove(&pref_group);
+        }
 
-        for pref_group in browser_pref_groups {
-            self.prefs_page.add(&pref_group);
+        let mut browser_pref_groups = self.build_browser_sections(app);
+        browser_pref_groups.push(self.build_broken_section(app));
+        for pref_group in &browser_pref_groups {
+            self.prefs_page.add(pref_group);
         }
+        *self.browser_sections.borrow_mut() = browser_pref_groups;
+    }
+
+    fn build_editor_section(self: &Rc<Self>, app: &Rc<App>) -> PreferencesGroup {
+        let button_content = ButtonContent::builder()
+            .label("New browser")
+            .icon_name("list-add-symbolic")
+            .build();
+        let new_browser_button = Button::builder()
+            .css_classes(["flat"])
+            .child(&button_content)
+            .build();
+
+        let self_clone = self.clone();
+        let app_clone = app.clone();
+        new_browser_button.connect_clicked(move |_| {
+            self_clone.show_editor(&app_clone, None);
+        });
+
+        PreferencesGroup::builder()
+            .description("Add a browser or change how a browser runs your web apps")
+            .header_suffix(&new_browser_button)
+            .build()
     }
 
-    fn build_browser_sections(app: &Rc<App>) -> Vec<PreferencesGroup> {
+    fn show_editor(self: &Rc<Self>, app: &Rc<App>, config_name: Option<&str>) {
+        let editor = match BrowserEditor::new(app, config_name) {
+            Ok(editor) => editor,
+            Err(error) => {
+                app.show_error(&error);
+                return;
+            }
+        };
+
+        let self_clone = self.clone();
+        editor.init(move || {
+            self_clone
+                .toast_overlay
+                .add_toast(Toast::new("Saved browser"));
+        });
+        editor.show();
+    }
+
+    fn build_browser_sections(self: &Rc<Self>, app: &Rc<App>) -> Vec<PreferencesGroup> {
         let flatpak_browsers = app.browser_configs.get_flatpak_browsers();
+        let snap_browsers = app.browser_configs.get_snap_browsers();
         let system_browsers = app.browser_configs.get_system_browsers();
+        let path_browsers = app.browser_configs.get_path_browsers();
         let uninstalled_browsers = app.browser_configs.get_uninstalled_browsers();
+        let is_detecting = app.browser_configs.is_detecting();
 
-        if flatpak_browsers.is_empty() && system_browsers.is_empty() {
+        if flatpak_browsers.is_empty()
+            && snap_browsers.is_empty()
+            && system_browsers.is_empty()
+            && path_browsers.is_empty()
+            && !is_detecting
+        {
             let status_page = StatusPage::builder()
                 .title("No compatible browsers found")
                 .description("Try installing one!")
@@ -72,21 +139,37 @@ impl BrowsersPage {
         }
 
         let flatpak_pref_group = PreferencesGroup::builder().title("Flatpak").build();
+        let snap_pref_group = PreferencesGroup::builder()
+            .title("Snap")
+            .visible(!snap_browsers.is_empty())
+            .build();
         let system_pref_group = PreferencesGroup::builder().title("System").build();
+        let path_pref_group = PreferencesGroup::builder()
+            .title("App images and executables")
+            .visible(!path_browsers.is_empty())
+            .build();
         let uninstalled_pref_group = PreferencesGroup::builder()
             .title("Supported but not installed")
             .build();
 
         for browser in &flatpak_browsers {
-            let browser_row = Self::build_browser_row(browser);
+            let browser_row = self.build_browser_row(app, browser);
             flatpak_pref_group.add(&browser_row);
         }
+        for browser in &snap_browsers {
+            let browser_row = self.build_browser_row(app, browser);
+            snap_pref_group.add(&browser_row);
+        }
         for browser in &system_browsers {
-            let browser_row = Self::build_browser_row(browser);
+            let browser_row = self.build_browser_row(app, browser);
             system_pref_group.add(&browser_row);
         }
+        for browser in &path_browsers {
+            let browser_row = self.build_browser_row(app, browser);
+            path_pref_group.add(&browser_row);
+        }
         for browser in &uninstalled_browsers {
-            let browser_row = Self::build_browser_row(browser);
+            let browser_row = self.build_browser_row(app, browser);
             uninstalled_pref_group.add(&browser_row);
         }
 
@@ -95,15 +178,88 @@ impl BrowsersPage {
         }
 
         Vec::from([
+            Self::build_detecting_section(is_detecting),
             flatpak_pref_group,
+            snap_pref_group,
             system_pref_group,
+            path_pref_group,
             uninstalled_pref_group,
         ])
     }
 
-    fn build_browser_row(browser: &Browser) -> ExpanderRow {
+    fn build_detecting_section(is_detecting: bool) -> PreferencesGroup {
+        let detecting_row = ActionRow::builder()
+            .title("Looking for installed browsers")
+            .build();
+        detecting_row.add_prefix(&Spinner::new());
+
+        let pref_group = PreferencesGroup::builder().visible(is_detecting).build();
+        pref_group.add(&detecting_row);
+
+        pref_group
+    }
+
+    /// Configs that could not be loaded, hidden when there are none
+    fn build_broken_section(self: &Rc<Self>, app: &Rc<App>) -> PreferencesGroup {
+        let broken_configs = app.browser_configs.get_broken_configs();
+        let pref_group = PreferencesGroup::builder()
+            .title("Broken configs")
+            .description("These configs could not be loaded, fix the errors to use them")
+            .visible(!broken_configs.is_empty())
+            .build();
+
+        for broken_config in &broken_configs {
+            let error_count = broken_config.errors.len();
+            let row = ExpanderRow::builder()
+                .title(&broken_config.config_name)
+                .subtitle(if error_count == 1 {
+                    "1 error".to_string()
+                } else {
+                    format!("{error_count} errors")
+                })
+                .build();
+            let icon = Image::from_icon_name("dialog-warning-symbolic");
+            icon.add_css_class("error");
+            row.add_prefix(&icon);
+            row.add_suffix(&self.build_edit_button(app, &broken_config.config_name));
+
+            for error in &broken_config.errors {
+                let error_row = ActionRow::builder()
+                    .title(&error.message)
+                    .subtitle(format!("{}:{}", error.path.display(), error.line))
+                    .use_markup(false)
+                    .subtitle_selectable(true)
+                    .build();
+                row.add_row(&error_row);
+            }
+
+            pref_group.add(&row);
+        }
+
+        pref_group
+    }
+
+    fn build_edit_button(self: &Rc<Self>, app: &Rc<App>, config_name: &str) -> Button {
+        let edit_button = Button::builder()
+            .icon_name("document-edit-symbolic")
+            .tooltip_text("Edit browser")
+            .valign(Align::Center)
+            .css_classes(["flat"])
+            .build();
+        let self_clone = self.clone();
+        let app_clone = app.clone();
+        let config_name = config_name.to_string();
+        edit_button.connect_clicked(move |_| {
+            self_clone.show_editor(&app_clone, Some(&config_name));
+        });
+
+        edit_button
+    }
+
+    fn build_browser_row(self: &Rc<Self>, app: &Rc<App>, browser: &Browser) -> ExpanderRow {
         let row = ExpanderRow::builder().title(&browser.name).build();
         row.add_prefix(&browser.get_icon());
+        row.add_suffix(&self.build_edit_button(app, &browser.config_name));
 
         let browser_expand = Self::build_browser_expand_content(browser);
         row.add_row(&browser_expand);
@@ -133,31 +289,24 @@ impl BrowsersPage {
         header_box.append(&app_label);
         content_box.append(&header_box);
 
-        if browser.is_flatpak() {
-            let mut label = String::new();
-
-            if let Some(flatpak_id) = &browser.flatpak_id {
-                let _ = write!(label, "{flatpak_id}");
-            }
-
-            let flatpak_label = Label::builder()
-                .label(&label)
-                .css_classes(["subtitle"])
-                .valign(Align::Center)
-                .build();
-
-            content_box.append(&flatpak_label);
-        }
-
-        if browser.is_system()
-            && let Some(executable) = &browser.executable
-        {
-            let executable_label = Label::builder()
-                .label(executable)
+        let installation_label = match browser.installation {
+            Installation::Flatpak => browser.flatpak_id.clone(),
+            Installation::Snap => browser.get_command().ok(),
+            Installation::System => browser.executable.clone(),
+            Installation::Path => browser
+                .executable_path
+                .as_ref()
+                .map(|executable_path| executable_path.to_string_lossy().to_string()),
+            Installation::None => None,
+        };
+        if let Some(installation_label) = installation_label {
+            let installation_label = Label::builder()
+                .label(installation_label)
                 .css_classes(["subtitle"])
                 .valign(Align::Center)
+                .wrap(true)
                 .build();
-            content_box.append(&executable_label);
+            content_box.append(&installation_label);
         }
 
         let mut capabilities_list = String::new();
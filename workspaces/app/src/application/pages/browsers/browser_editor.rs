@@ -0,0 +1,524 @@
+use crate::application::App;
+use anyhow::{Context, Result, bail};
+use common::{
+    browsers::{Browser, BrowserConfigs, BrowserYaml},
+    desktop_file::DesktopFile,
+};
+use gtk::{
+    Button, FileDialog, StringList, TextBuffer, TextView, WrapMode, gio,
+    glib::{self, VariantTy, variant::ToVariant},
+    prelude::{ButtonExt, EditableExt, FileExt, TextBufferExt, TextViewExt, WidgetExt},
+};
+use libadwaita::{
+    ButtonContent, ComboRow, Dialog, EntryRow, HeaderBar, PreferencesGroup, PreferencesPage,
+    SwitchRow, Toast, ToastOverlay, ToolbarView,
+    prelude::{AdwDialogExt, ComboRowExt, PreferencesGroupExt, PreferencesPageExt},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    ffi::OsString,
+    os::unix::ffi::OsStringExt,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use tracing::error;
+
+/// Edits a browser config and its desktop file template, saved in the user config layer
+pub struct BrowserEditor {
+    app: Rc<App>,
+    is_new: bool,
+    dialog: Dialog,
+    toast_overlay: ToastOverlay,
+    save_button: Button,
+    config_name_row: EntryRow,
+    name_row: EntryRow,
+    flatpak_row: EntryRow,
+    snap_row: EntryRow,
+    system_bin_row: EntryRow,
+    base_row: ComboRow,
+    can_isolate_row: SwitchRow,
+    can_start_maximized_row: SwitchRow,
+    prefix_row: EntryRow,
+    executable_paths_buffer: TextBuffer,
+    issues_buffer: TextBuffer,
+    template_buffer: TextBuffer,
+    preview_view: TextView,
+    on_save: RefCell<Option<Box<dyn Fn()>>>,
+}
+impl BrowserEditor {
+    /// Starting point for a new browser
+    const NEW_TEMPLATE: &str = "[Desktop Entry]
+Version=1.0
+Type=Application
+Terminal=false
+Name=%{name}
+Exec=%{command} %{url}
+Icon=%{icon}
+StartupWMClass=%{app_id}
+";
+
+    /// Loads the config with this name, or starts a new browser without one
+    pub fn new(app: &Rc<App>, config_name: Option<&str>) -> Result<Rc<Self>> {
+        let is_new = config_name.is_none();
+        let (yaml, template) = match config_name {
+            Some(config_name) => app.browser_configs.read_config(config_name)?,
+            None => (
+                BrowserYaml {
+                    base: "chromium".to_string(),
+                    ..Default::default()
+                },
+                Self::NEW_TEMPLATE.to_string(),
+            ),
+        };
+
+        let config_name_row = EntryRow::builder()
+            .title("Config name")
+            .text(config_name.unwrap_or_default())
+            .build();
+        config_name_row.set_editable(is_new);
+        let name_row = EntryRow::builder().title("Name").text(&yaml.name).build();
+        let flatpak_row = Self::build_optional_row("Flatpak ID", yaml.flatpak.as_deref());
+        let snap_row = Self::build_optional_row("Snap name", yaml.snap.as_deref());
+        let system_bin_row = Self::build_optional_row("System binary", yaml.system_bin.as_deref());
+
+        let base_row = Self::build_base_row(&yaml.base);
+
+        let can_isolate_row = SwitchRow::builder()
+            .title("Can isolate")
+            .subtitle("Web apps can use their own profile")
+            .active(yaml.can_isolate)
+            .build();
+        let can_start_maximized_row = SwitchRow::builder()
+            .title("Can start maximized")
+            .active(yaml.can_start_maximized)
+            .build();
+        let prefix_row = EntryRow::builder()
+            .title("Desktop file name prefix")
+            .text(&yaml.desktop_file_name_prefix)
+            .build();
+
+        let issues_buffer = TextBuffer::builder().text(yaml.issues.join("\n")).build();
+        let template_buffer = TextBuffer::builder().text(&template).build();
+        let preview_view = Self::build_text_view(&TextBuffer::new(None), false);
+
+        let browser_group = Self::build_browser_group(&[
+            &config_name_row,
+            &name_row,
+            &flatpak_row,
+            &snap_row,
+            &system_bin_row,
+        ]);
+
+        let features_group = PreferencesGroup::builder().title("Features").build();
+        features_group.add(&base_row);
+        features_group.add(&can_isolate_row);
+        features_group.add(&can_start_maximized_row);
+        features_group.add(&prefix_row);
+
+        let (executable_paths_group, executable_paths_buffer) =
+            Self::build_executable_paths_group(app, &yaml.executable_paths);
+
+        let prefs_page = PreferencesPage::new();
+        prefs_page.add(&browser_group);
+        prefs_page.add(&executable_paths_group);
+        prefs_page.add(&features_group);
+        prefs_page.add(&Self::build_text_group(
+            "Known issues",
+            "One per line",
+            &Self::build_text_view(&issues_buffer, true),
+        ));
+        prefs_page.add(&Self::build_text_group(
+            "Desktop file template",
+            "Filled in for every web app, see the README for the variables",
+            &Self::build_text_view(&template_buffer, true),
+        ));
+        prefs_page.add(&Self::build_text_group(
+            "Preview",
+            "Generated for an example app",
+            &preview_view,
+        ));
+
+        let toast_overlay = ToastOverlay::new();
+        toast_overlay.set_child(Some(&prefs_page));
+
+        let (dialog, save_button) = Self::build_dialog(is_new, &toast_overlay);
+
+        Ok(Rc::new(Self {
+            app: app.clone(),
+            is_new,
+            dialog,
+            toast_overlay,
+            save_button,
+            config_name_row,
+            name_row,
+            flatpak_row,
+            snap_row,
+            system_bin_row,
+            base_row,
+            can_isolate_row,
+            can_start_maximized_row,
+            prefix_row,
+            executable_paths_buffer,
+            issues_buffer,
+            template_buffer,
+            preview_view,
+            on_save: RefCell::new(None),
+        }))
+    }
+
+    pub fn init<OnSave>(self: &Rc<Self>, on_save: OnSave)
+    where
+        OnSave: Fn() + 'static,
+    {
+        *self.on_save.borrow_mut() = Some(Box::new(on_save));
+
+        for entry_row in [
+            &self.config_name_row,
+            &self.name_row,
+            &self.flatpak_row,
+            &self.snap_row,
+            &self.system_bin_row,
+            &self.prefix_row,
+        ] {
+            let self_clone = self.clone();
+            entry_row.connect_changed(move |_| self_clone.reload_preview());
+        }
+        for switch_row in [&self.can_isolate_row, &self.can_start_maximized_row] {
+            let self_clone = self.clone();
+            switch_row.connect_active_notify(move |_| self_clone.reload_preview());
+        }
+        let self_clone = self.clone();
+        self.base_row
+            .connect_selected_notify(move |_| self_clone.reload_preview());
+        for buffer in [
+            &self.executable_paths_buffer,
+            &self.issues_buffer,
+            &self.template_buffer,
+        ] {
+            let self_clone = self.clone();
+            buffer.connect_changed(move |_| self_clone.reload_preview());
+        }
+
+        let self_clone = self.clone();
+        self.save_button.connect_clicked(move |_| {
+            if let Err(error) = self_clone.save() {
+                error!("Failed to save browser config: {error:?}");
+                self_clone.toast_overlay.dismiss_all();
+                self_clone
+                    .toast_overlay
+                    .add_toast(Toast::new(&error.to_string()));
+            }
+        });
+
+        self.reload_preview();
+    }
+
+    pub fn show(&self) {
+        self.dialog.present(Some(&self.app.window.adw_window));
+    }
+
+    /// An unknown base is the same as `none`
+    fn build_base_row(base: &str) -> ComboRow {
+        let base_row = ComboRow::builder()
+            .title("Base")
+            .subtitle("Browser engine, for profiles and app names")
+            .model(&StringList::new(&BrowserYaml::BASES))
+            .build();
+        let base_index = BrowserYaml::BASES
+            .iter()
+            .position(|known_base| *known_base == base)
+            .unwrap_or(BrowserYaml::BASES.len() - 1);
+        base_row.set_selected(u32::try_from(base_index).unwrap_or_default());
+
+        base_row
+    }
+
+    fn build_dialog(is_new: bool, content: &ToastOverlay) -> (Dialog, Button) {
+        let cancel_button = Button::builder()
+            .label("_Cancel")
+            .use_underline(true)
+            .build();
+        let save_button = Button::builder()
+            .label("_Save")
+            .use_underline(true)
+            .css_classes(["suggested-action"])
+            .build();
+        let header = HeaderBar::builder()
+            .show_start_title_buttons(false)
+            .show_end_title_buttons(false)
+            .build();
+        header.pack_start(&cancel_button);
+        header.pack_end(&save_button);
+
+        let toolbar = ToolbarView::builder().content(content).build();
+        toolbar.add_top_bar(&header);
+
+        let dialog = Dialog::builder()
+            .title(if is_new {
+                "New browser"
+            } else {
+                "Edit browser"
+            })
+            .content_width(600)
+            .content_height(700)
+            .child(&toolbar)
+            .build();
+
+        let dialog_clone = dialog.clone();
+        cancel_button.connect_clicked(move |_| {
+            dialog_clone.close();
+        });
+
+        (dialog, save_button)
+    }
+
+    /// The choose file button adds the picked file as a line
+    fn build_executable_paths_group(
+        app: &Rc<App>,
+        executable_paths: &[String],
+    ) -> (PreferencesGroup, TextBuffer) {
+        let buffer = TextBuffer::builder()
+            .text(executable_paths.join("\n"))
+            .build();
+        let pref_group = Self::build_text_group(
+            "Executable paths",
+            "An app image or a browser in a folder like /opt, one per line. A * in the file name matches any version.",
+            &Self::build_text_view(&buffer, true),
+        );
+
+        let button_content = ButtonContent::builder()
+            .label("Choose file")
+            .icon_name("document-open-symbolic")
+            .build();
+        let choose_file_button = Button::builder()
+            .css_classes(["flat"])
+            .child(&button_content)
+            .build();
+
+        let app_clone = app.clone();
+        let buffer_clone = buffer.clone();
+        choose_file_button.connect_clicked(move |_| {
+            let app = app_clone.clone();
+            let buffer = buffer_clone.clone();
+            glib::spawn_future_local(async move {
+                let file_dialog = FileDialog::builder()
+                    .title("Choose a browser executable")
+                    .modal(true)
+                    .build();
+                let Ok(file) = file_dialog.open_future(Some(&app.window.adw_window)).await else {
+                    return;
+                };
+                let Some(path) = file.path() else {
+                    return;
+                };
+                let path = match Self::get_host_path(&path).await {
+                    Ok(path) => path,
+                    Err(error) => {
+                        app.show_error(&error);
+                        return;
+                    }
+                };
+
+                let mut end = buffer.end_iter();
+                if end.offset() > 0 {
+                    buffer.insert(&mut end, "\n");
+                }
+                buffer.insert(&mut end, &path.to_string_lossy());
+            });
+        });
+        pref_group.set_header_suffix(Some(&choose_file_button));
+
+        (pref_group, buffer)
+    }
+
+    /// Files picked inside the sandbox are shared through the document portal, the browser
+    /// runs on the host so it needs the real path
+    async fn get_host_path(path: &Path) -> Result<PathBuf> {
+        let Ok(portal_path) = path.strip_prefix(glib::user_runtime_dir().join("doc")) else {
+            return Ok(path.to_path_buf());
+        };
+        let document_id = portal_path
+            .iter()
+            .next()
+            .context("No document id in portal path")?
+            .to_string_lossy()
+            .to_string();
+
+        let connection = gio::bus_get_future(gio::BusType::Session).await?;
+        let reply = connection
+            .call_future(
+                Some("org.freedesktop.portal.Documents"),
+                "/org/freedesktop/portal/documents",
+                "org.freedesktop.portal.Documents",
+                "GetHostPaths",
+                Some(&(vec![document_id.clone()],).to_variant()),
+                Some(VariantTy::new("(a{say})")?),
+                gio::DBusCallFlags::NONE,
+                -1,
+            )
+            .await
+            .context("Failed to get the path of the file on the host")?;
+        let host_paths: HashMap<String, Vec<u8>> = reply
+            .child_value(0)
+            .get()
+            .context("Invalid reply of the document portal")?;
+        let mut host_path = host_paths
+            .get(&document_id)
+            .context("The document portal does not know the file")?
+            .clone();
+        // Byte strings of D-Bus end with a nul
+        if host_path.last() == Some(&0) {
+            host_path.pop();
+        }
+
+        Ok(PathBuf::from(OsString::from_vec(host_path)))
+    }
+
+    /// Left empty when the config does not have it
+    fn build_optional_row(title: &str, text: Option<&str>) -> EntryRow {
+        EntryRow::builder()
+            .title(title)
+            .text(text.unwrap_or_default())
+            .build()
+    }
+
+    fn build_browser_group(entry_rows: &[&EntryRow]) -> PreferencesGroup {
+        let pref_group = PreferencesGroup::builder()
+            .title("Browser")
+            .description(
+                "Saved in your config folder, it replaces a shipped config with the same name",
+            )
+            .build();
+        for entry_row in entry_rows {
+            pref_group.add(*entry_row);
+        }
+
+        pref_group
+    }
+
+    fn build_text_group(title: &str, description: &str, text_view: &TextView) -> PreferencesGroup {
+        let pref_group = PreferencesGroup::builder()
+            .title(title)
+            .description(description)
+            .build();
+        pref_group.add(text_view);
+
+        pref_group
+    }
+
+    fn build_text_view(buffer: &TextBuffer, editable: bool) -> TextView {
+        TextView::builder()
+            .buffer(buffer)
+            .editable(editable)
+            .monospace(true)
+            .wrap_mode(WrapMode::WordChar)
+            .height_request(80)
+            .top_margin(12)
+            .bottom_margin(12)
+            .left_margin(12)
+            .right_margin(12)
+            .css_classes(["card"])
+            .build()
+    }
+
+    fn get_config_name(&self) -> String {
+        self.config_name_row.text().trim().to_string()
+    }
+
+    fn get_template(&self) -> String {
+        let buffer = &self.template_buffer;
+        buffer
+            .text(&buffer.start_iter(), &buffer.end_iter(), false)
+            .to_string()
+    }
+
+    fn get_yaml(&self) -> BrowserYaml {
+        let optional_text = |entry_row: &EntryRow| {
+            Some(entry_row.text().trim().to_string()).filter(|text| !text.is_empty())
+        };
+        let get_lines = |buffer: &TextBuffer| {
+            buffer
+                .text(&buffer.start_iter(), &buffer.end_iter(), false)
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let base = usize::try_from(self.base_row.selected())
+            .ok()
+            .and_then(|index| BrowserYaml::BASES.get(index))
+            .unwrap_or(&"none");
+
+        BrowserYaml {
+            name: self.name_row.text().trim().to_string(),
+            flatpak: optional_text(&self.flatpak_row),
+            snap: optional_text(&self.snap_row),
+            system_bin: optional_text(&self.system_bin_row),
+            executable_paths: get_lines(&self.executable_paths_buffer),
+            can_isolate: self.can_isolate_row.is_active(),
+            can_start_maximized: self.can_start_maximized_row.is_active(),
+            desktop_file_name_prefix: self.prefix_row.text().trim().to_string(),
+            base: (*base).to_string(),
+            issues: get_lines(&self.issues_buffer),
+        }
+    }
+
+    /// The same checks as saving, so the preview shows why it can't be saved
+    fn build_preview(&self) -> Result<String> {
+        let config_name = self.get_config_name();
+        if !BrowserConfigs::is_valid_config_name(&config_name) {
+            bail!("Config name can only have letters, numbers, '-' and '_'")
+        }
+
+        let browser = Rc::new(Browser::from_yaml(
+            &config_name,
+            &self.get_yaml(),
+            &self.get_template(),
+            &self.app.browser_configs,
+            &self.app.dirs,
+        )?);
+        let preview =
+            DesktopFile::preview_browser(&browser, &self.app.browser_configs, &self.app.dirs)?;
+
+        Ok(preview)
+    }
+
+    fn reload_preview(&self) {
+        let preview = self.build_preview();
+        let text = match &preview {
+            Ok(preview) => preview.clone(),
+            Err(error) => format!("{error:#}"),
+        };
+
+        self.preview_view.buffer().set_text(&text);
+        if preview.is_ok() {
+            self.preview_view.remove_css_class("error");
+        } else {
+            self.preview_view.add_css_class("error");
+        }
+        self.save_button.set_sensitive(preview.is_ok());
+    }
+
+    fn save(&self) -> Result<()> {
+        let config_name = self.get_config_name();
+        if self.is_new && self.app.browser_configs.read_config(&config_name).is_ok() {
+            bail!("A browser config named '{config_name}' already exists")
+        }
+
+        self.app.browser_configs.save_user_config(
+            &config_name,
+            &self.get_yaml(),
+            &self.get_template(),
+        )?;
+        self.app.reload_browsers();
+
+        if let Some(on_save) = self.on_save.borrow().as_ref() {
+            on_save();
+        }
+        self.dialog.close();
+
+        Ok(())
+    }
+}
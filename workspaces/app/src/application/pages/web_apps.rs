@@ -1,7 +1,7 @@
 mod web_app_view;
 
 use super::NavPage;
-use crate::application::{App, pages::PrefNavPage};
+use crate::application::{App, icons::DesktopFileIcon, pages::PrefNavPage};
 use common::{
     desktop_file::{DesktopFile, DesktopFileError},
     utils,
@@ -16,7 +16,7 @@ use libadwaita::{
     prelude::{ActionRowExt, PreferencesGroupExt, PreferencesPageExt},
 };
 use std::{cell::RefCell, rc::Rc};
-use tracing::{debug, error};
+use tracing::{debug, error, info};
 use web_app_view::WebAppView;
 
 pub struct WebAppsPage {
@@ -25,6 +25,8 @@ pub struct WebAppsPage {
     nav_view: Rc<NavigationView>,
     prefs_page: PreferencesPage,
     app_section: RefCell<PreferencesGroup>,
+    /// Pushed on the navigation view, these get the browsers as they are found
+    app_views: RefCell<Vec<Rc<WebAppView>>>,
 }
 impl NavPage for WebAppsPage {
     fn get_navpage(&self) -> &NavigationPage {
@@ -55,6 +57,7 @@ impl WebAppsPage {
             nav_view: Rc::new(nav_view),
             prefs_page,
             app_section,
+            app_views: RefCell::new(Vec::new()),
         })
     }
 
@@ -66,8 +69,29 @@ impl WebAppsPage {
         let self_clone = self.clone();
         let app_clone = app.clone();
 
-        self.nav_view
-            .connect_popped(move |_, _| self_clone.reset_app_section(&app_clone));
+        self.nav_view.connect_popped(move |_, popped_page| {
+            self_clone
+                .app_views
+                .borrow_mut()
+                .retain(|app_view| app_view.get_navpage() != popped_page);
+            self_clone.reset_app_section(&app_clone);
+        });
+    }
+
+    pub fn on_browsers_change(self: &Rc<Self>, app: &Rc<App>) {
+        for app_view in self.app_views.borrow().iter() {
+            app_view.reload_browsers();
+        }
+
+        // Desktop files are only updated with all browsers known
+        if !app.browser_configs.is_detecting() {
+            self.reset_app_section(app);
+        }
+    }
+
+    fn push_app_view(&self, app_view: &Rc<WebAppView>) {
+        self.nav_view.push(app_view.get_navpage());
+        self.app_views.borrow_mut().push(app_view.clone());
     }
 
     fn build_apps_section(self: Rc<Self>, app: &Rc<App>) -> PreferencesGroup {
@@ -99,7 +123,7 @@ impl WebAppsPage {
                 }
             });
 
-            self_clone.nav_view.push(nav_page);
+            self_clone.push_app_view(&app_page);
         });
 
         let pref_group = PreferencesGroup::builder()
@@ -159,7 +183,7 @@ impl WebAppsPage {
             let app_page =
                 WebAppView::new(&app_clone, &nav_view_clone, &desktop_file.clone(), false);
             app_page.init();
-            self.nav_view.push(app_page.get_navpage());
+            self.push_app_view(&app_page);
         });
 
         app_row
@@ -171,6 +195,7 @@ impl WebAppsPage {
         let mut owned_desktop_files = Vec::new();
         let applications_path = app.dirs.applications();
         let mut app_has_updated = false;
+        let is_detecting = app.browser_configs.is_detecting();
 
         for file in utils::files::get_entries_in_dir(&applications_path).unwrap_or_default() {
             let Ok(mut desktop_file) =
@@ -192,6 +217,21 @@ impl WebAppsPage {
 
             debug!(file_name = &file_name, "Found desktop file");
 
+            if is_detecting {
+                owned_desktop_files.push(Rc::new(RefCell::new(desktop_file)));
+                continue;
+            }
+
+            match desktop_file.migrate_to_snap() {
+                Ok(true) => info!(file_name = &file_name, "Moved desktop file to snap browser"),
+                Ok(false) => {}
+                Err(error) => error!(
+                    error = error.to_string(),
+                    desktop_file = &file_name,
+                    "Failed to move 'DesktopFile' to snap browser"
+                ),
+            }
+
             let is_updated = match desktop_file.update() {
                 Ok(is_updated) => is_updated,
                 Err(error) => {
@@ -1,18 +1,21 @@
 mod icon_picker;
+mod quick_links;
 
 use crate::application::{
     App,
+    icons::{BrowserIcon, DesktopFileIcon},
     pages::{NavPage, PrefPage},
 };
 use common::{
     browsers::{Base, Browser},
-    desktop_file::{DesktopFile, DesktopFileError},
-    utils,
+    desktop_file::{DesktopAction, DesktopFile, DesktopFileError},
+    url::UrlExt,
+    utils::{self, command::HostCommand},
 };
 use gtk::{
     Align, EventControllerMotion, ListItem, SignalListItemFactory, gio,
     glib::{self, BoxedAnyObject, object::Cast},
-    prelude::ListItemExt,
+    prelude::{ListItemExt, ListModelExt},
 };
 use icon_picker::IconPicker;
 use libadwaita::{
@@ -28,12 +31,12 @@ use libadwaita::{
         PreferencesRowExt,
     },
 };
+use quick_links::QuickLinks;
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     path::{Path, PathBuf},
     rc::Rc,
 };
-use std::{fmt::Write as _, fs};
 use tracing::{debug, error};
 use url::Url;
 
@@ -58,7 +61,10 @@ pub struct WebAppView {
     isolate_row: SwitchRow,
     maximize_row: SwitchRow,
     browser_row: ComboRow,
+    /// Keeps the desktop file as it is while the browser list is replaced
+    is_reloading_browsers: Cell<bool>,
     icon_picker: RefCell<Option<Rc<IconPicker>>>,
+    quick_links: Rc<QuickLinks>,
 }
 impl NavPage for WebAppView {
     fn get_navpage(&self) -> &NavigationPage {
@@ -110,6 +116,7 @@ impl WebAppView {
         let isolate_row = Self::build_isolate_row(desktop_file, browser_can_isolate);
         let maximize_row = Self::build_maximize_row(desktop_file, browser_can_maximize);
         let browser_row = Self::build_browser_row(app, desktop_file);
+        let quick_links = QuickLinks::new(app, desktop_file);
 
         Rc::new(Self {
             is_new: RefCell::new(is_new),
@@ -132,7 +139,9 @@ impl WebAppView {
             isolate_row,
             maximize_row,
             browser_row,
+            is_reloading_browsers: Cell::new(false),
             icon_picker: RefCell::new(None),
+            quick_links,
         })
     }
 
@@ -149,6 +158,7 @@ impl WebAppView {
         let mut pref_groups_borrow = self.pref_groups.borrow_mut();
         pref_groups_borrow.push(web_app_header);
         pref_groups_borrow.push(general_pref_group);
+        pref_groups_borrow.push(self.quick_links.get_pref_group().clone());
         pref_groups_borrow.push(button_footer);
 
         for pref_group in pref_groups_borrow.iter() {
@@ -158,6 +168,7 @@ impl WebAppView {
 
         self.connect_change_icon_button();
         self.connect_run_app_button();
+        self.connect_quick_links();
     }
 
     pub fn get_is_new(self: &Rc<Self>) -> bool {
@@ -201,6 +212,7 @@ impl WebAppView {
         self.url_row.set_text(&url);
         self.isolate_row.set_active(is_isolated);
         self.browser_row.set_selected(browser_index);
+        self.quick_links.reload();
 
         self.on_desktop_file_change();
 
@@ -378,11 +390,6 @@ impl WebAppView {
         // Some weird factory setup where the list calls factory methods...
         // First create all data structures, then set data from ListStore.
         // Why is this so unnecessary complicated? ¯\_(ツ)_/¯
-        let list = gio::ListStore::new::<BoxedAnyObject>();
-        for browser in &all_browsers {
-            let boxed = BoxedAnyObject::new(browser.clone());
-            list.append(&boxed);
-        }
         let factory = SignalListItemFactory::new();
         factory.connect_bind(|_, list_item| {
             let list_item = list_item.downcast_ref::<ListItem>().unwrap();
@@ -411,17 +418,21 @@ impl WebAppView {
         let combo_row = ComboRow::builder()
             .title("Browser")
             .subtitle("Pick a browser")
-            .model(&list)
+            .model(&Self::build_browser_list(app))
             .factory(&factory)
             .build();
 
-        if let Some(browser_index) = desktop_file
-            .borrow()
+        let desktop_file_borrow = desktop_file.borrow();
+        let browser_index = desktop_file_borrow
             .get_browser()
-            .and_then(|browser| browser.get_index())
-        {
+            .and_then(|browser| browser.get_index());
+        let is_browser_pending =
+            app.browser_configs.is_detecting() && desktop_file_borrow.get_browser_id().is_some();
+        drop(desktop_file_borrow);
+
+        if let Some(browser_index) = browser_index {
             combo_row.set_selected(browser_index.try_into().unwrap());
-        } else if let Some(browser) = all_browsers.first() {
+        } else if !is_browser_pending && let Some(browser) = all_browsers.first() {
             // ComboRow has already a selected item on load, so sync this if empty.
             desktop_file.borrow_mut().set_browser(browser);
         }
@@ -429,6 +440,62 @@ impl WebAppView {
         combo_row
     }
 
+    fn build_browser_list(app: &Rc<App>) -> gio::ListStore {
+        let list = gio::ListStore::new::<BoxedAnyObject>();
+        for browser in &app.browser_configs.get_all_browsers() {
+            let boxed = BoxedAnyObject::new(browser.clone());
+            list.append(&boxed);
+        }
+
+        list
+    }
+
+    /// Browsers are found after the view is built. A new app gets the first one found.
+    /// An existing app only gets its own browser, a different one would remove its profile.
+    pub fn reload_browsers(self: &Rc<Self>) {
+        debug!("Reloading browsers");
+
+        self.is_reloading_browsers.set(true);
+        let browser_list = Self::build_browser_list(&self.app);
+        self.browser_row.set_model(Some(&browser_list));
+
+        let is_detecting = self.app.browser_configs.is_detecting();
+        let first_browser = self.app.browser_configs.get_all_browsers().first().cloned();
+        let browser = self
+            .desktop_file
+            .borrow()
+            .get_browser()
+            .filter(|browser| browser.is_installed())
+            .or(match (is_detecting, *self.is_new.borrow()) {
+                (false, true) => first_browser,
+                (true, true) => first_browser.filter(|browser| browser.is_installed()),
+                (_, false) => None,
+            });
+        let Some(browser) = browser else {
+            // Show "No browser" instead of a browser the app doesn't use
+            if !is_detecting {
+                self.browser_row
+                    .set_selected(browser_list.n_items().saturating_sub(1));
+            }
+            self.is_reloading_browsers.set(false);
+            return;
+        };
+
+        self.browser_row
+            .set_selected(browser.get_index().unwrap_or_default().try_into().unwrap());
+        let mut desktop_file_borrow = self.desktop_file.borrow_mut();
+        desktop_file_borrow.set_browser(&browser);
+        let is_isolated = desktop_file_borrow.get_isolated().unwrap_or(false);
+        let is_maximized = desktop_file_borrow.get_maximized().unwrap_or(false);
+        drop(desktop_file_borrow);
+        self.isolate_row.set_active(is_isolated);
+        self.maximize_row.set_active(is_maximized);
+        self.is_reloading_browsers.set(false);
+
+        self.on_isolation_change();
+        self.on_desktop_file_change();
+    }
+
     fn build_button_footer(self: &Rc<Self>) -> PreferencesGroup {
         fn button_wrap_box(button: &Button) -> WrapBox {
             let wrapbox = WrapBox::builder()
@@ -547,7 +614,7 @@ impl WebAppView {
                     // Success
                     self_clone_success.on_desktop_file_change();
                 }),
-                Some(move || {
+                Some(move |error: &anyhow::Error| {
                     // Fail
                     let undo_icon_path = undo_icon_path_fail.clone();
                     self_clone_fail
@@ -556,7 +623,7 @@ impl WebAppView {
                         .set_icon_path(Path::new(&undo_icon_path));
 
                     self_clone_fail.on_desktop_file_change();
-                    self_clone_fail.on_error("Failed to save icon", None);
+                    self_clone_fail.on_error(&format!("Failed to save icon: {error:#}"), None);
                 }),
             );
         });
@@ -567,7 +634,7 @@ impl WebAppView {
 
         self.run_app_button.connect_clicked(move |_| {
             let desktop_file_borrow = self_clone.desktop_file.borrow();
-            let Some(mut executable) = desktop_file_borrow.get_exec() else {
+            let Some(mut run_args) = desktop_file_borrow.get_run_args() else {
                 return;
             };
 
@@ -576,15 +643,17 @@ impl WebAppView {
                     .get_browser()
                     .is_some_and(|browser| browser.base == Base::Chromium)
                 {
-                    let _ = write!(executable, " --no-sandbox");
+                    run_args.push("--no-sandbox".to_string());
                 }
                 debug!("Running in dev-container");
             }
 
-            debug!("Running web app: '{executable}'");
-            if let Err(error) = utils::command::run_command_background(&executable) {
+            debug!("Running web app: '{run_args:?}'");
+            if let Err(error) =
+                HostCommand::from_args(&run_args).and_then(|command| command.run_background())
+            {
                 error!(
-                    executable = executable,
+                    executable = format!("{run_args:?}"),
                     error = error.to_string(),
                     "Failed to run app"
                 );
@@ -613,8 +682,10 @@ impl WebAppView {
                     .unwrap_or_default()
             );
 
-            if !*self_clone.is_new.borrow() && self_clone.desktop_file.borrow().delete().is_err() {
-                self_clone.on_error("Failed to delete all files", None);
+            if !*self_clone.is_new.borrow()
+                && let Err(error) = self_clone.desktop_file.borrow().delete()
+            {
+                self_clone.on_error(&format!("Failed to delete all files: {error}"), None);
             }
 
             self_clone.nav_view.pop();
@@ -674,7 +745,7 @@ impl WebAppView {
 
         self.url_row.connect_changed(move |entry_row| {
             let input = entry_row.text().to_string();
-            let is_valid = Url::parse(&input).is_ok();
+            let is_valid = Url::parse(&input).is_ok_and(|url| url.is_web_url());
 
             debug!(is_valid, input, "Validate input: {}", entry_row.title());
 
@@ -743,6 +814,7 @@ impl WebAppView {
                 if *running_icon_search_id_clone.borrow() != run_id {
                     return;
                 }
+                self_clone.apply_manifest();
                 self_clone.on_desktop_file_change();
                 spinner_clone.set_visible(false);
                 self_clone.change_icon_button.set_sensitive(true);
@@ -750,10 +822,65 @@ impl WebAppView {
         });
     }
 
+    /// Fill in the web app with the site's manifest
+    fn apply_manifest(self: &Rc<Self>) {
+        let Some(manifest) = self.get_icon_picker().get_manifest() else {
+            return;
+        };
+        debug!(url = manifest.url.to_string(), "Applying manifest");
+
+        // Existing apps keep their quick links, also when the user removed all of them
+        if !*self.is_new.borrow() {
+            return;
+        }
+
+        if self.desktop_file.borrow().get_actions().is_empty() && !manifest.shortcuts.is_empty() {
+            let actions = manifest
+                .shortcuts
+                .iter()
+                .map(|shortcut| DesktopAction {
+                    name: shortcut.name.clone(),
+                    url: shortcut.url.to_string(),
+                })
+                .collect::<Vec<_>>();
+            self.quick_links.set_actions(&actions);
+        }
+
+        if self.name_row.text().is_empty()
+            && let Some(name) = manifest.get_name()
+        {
+            self.desktop_file.borrow_mut().set_name(name);
+            self.name_row.set_text(name);
+            self.nav_page.set_title(name);
+        }
+
+        if let Some(start_url) = &manifest.start_url
+            && start_url.as_str() != self.url_row.text()
+        {
+            self.desktop_file.borrow_mut().set_url(start_url.as_str());
+            self.url_row.set_text(start_url.as_str());
+        }
+
+        if let Some(description) = &manifest.description {
+            self.desktop_file.borrow_mut().set_comment(description);
+        }
+    }
+
+    fn connect_quick_links(self: &Rc<Self>) {
+        let self_clone = self.clone();
+
+        self.quick_links.init(move || {
+            self_clone.on_desktop_file_change();
+        });
+    }
+
     fn connect_isolate_row(self: &Rc<Self>) {
         let self_clone = self.clone();
 
         self.isolate_row.connect_active_notify(move |switch_row| {
+            if self_clone.is_reloading_browsers.get() {
+                return;
+            }
             self_clone
                 .desktop_file
                 .borrow_mut()
@@ -768,6 +895,9 @@ impl WebAppView {
         let self_clone = self.clone();
 
         self.maximize_row.connect_active_notify(move |switch_row| {
+            if self_clone.is_reloading_browsers.get() {
+                return;
+            }
             self_clone
                 .desktop_file
                 .borrow_mut()
@@ -783,6 +913,9 @@ impl WebAppView {
 
         self.browser_row
             .connect_selected_item_notify(move |combo_row| {
+                if self_clone.is_reloading_browsers.get() {
+                    return;
+                }
                 let selected_item = combo_row.selected_item();
                 let Some(selected_item) = selected_item else {
                     return;
@@ -829,12 +962,13 @@ impl WebAppView {
     }
 
     fn reset_browser_isolation(self: &Rc<Self>) {
-        let browser_can_isolate = self
-            .desktop_file
-            .borrow()
-            .get_browser()
-            .is_some_and(|browser| browser.can_isolate);
+        let browser = self.desktop_file.borrow().get_browser();
+        let browser_can_isolate = browser.as_ref().is_some_and(|browser| browser.can_isolate);
         self.isolate_row.set_sensitive(browser_can_isolate);
+        if browser.is_none() && self.app.browser_configs.is_detecting() {
+            // Turning it off would remove the profile of a browser that is not found yet
+            return;
+        }
         if browser_can_isolate {
             self.isolate_row.set_has_tooltip(false);
         } else {
@@ -844,12 +978,14 @@ impl WebAppView {
     }
 
     fn reset_browser_maximize(self: &Rc<Self>) {
-        let browser_can_maximize = self
-            .desktop_file
-            .borrow()
-            .get_browser()
+        let browser = self.desktop_file.borrow().get_browser();
+        let browser_can_maximize = browser
+            .as_ref()
             .is_some_and(|browser| browser.can_start_maximized);
         self.maximize_row.set_sensitive(browser_can_maximize);
+        if browser.is_none() && self.app.browser_configs.is_detecting() {
+            return;
+        }
 
         if browser_can_maximize {
             self.maximize_row.set_has_tooltip(false);
@@ -974,7 +1110,9 @@ impl WebAppView {
                 path = old_profile_path.display().to_string(),
                 "Deleting profile"
             );
-            let _ = fs::remove_dir_all(old_profile_path);
+            if let Err(error) = desktop_file_borrow.remove_profile(&old_profile_path) {
+                self.on_error(&format!("Old profile not removed: {error}"), None);
+            }
         }
 
         desktop_file_borrow.set_profile_path(&new_profile_path);
@@ -1,34 +1,165 @@
+mod certificate_group;
+mod icon_editor;
 mod icon_fetcher;
+mod image_header;
+mod theme_icon_group;
 
 use crate::application::App;
 use anyhow::{Context, Result, bail};
-use common::desktop_file::{DesktopFile, Icon};
+use certificate_group::CertificateGroup;
+use common::{desktop_file::DesktopFile, manifest::WebAppManifest, monogram};
 use gtk::{
     self, Align, Button, ContentFit, FileDialog, FileFilter, FlowBox, FlowBoxChild, Label,
     Orientation, Picture, SelectionMode,
-    gdk_pixbuf::Pixbuf,
+    gdk::RGBA,
+    gdk_pixbuf::{Pixbuf, PixbufLoader, prelude::PixbufLoaderExt},
     gio::prelude::FileExt,
     glib::object::Cast,
     prelude::{BoxExt, ButtonExt, FlowBoxChildExt, ListBoxRowExt, WidgetExt},
 };
-use icon_fetcher::IconFetcher;
+use icon_editor::IconEditor;
+use icon_fetcher::{IconFetcher, IconProgress, IconSource};
+use image_header::{ImageFormat, ImageHeader};
 use libadwaita::{
     AlertDialog, ButtonContent, ButtonRow, PreferencesGroup, PreferencesPage, PreferencesRow,
     ResponseAppearance, Spinner, StatusPage,
-    gio::Cancellable,
+    gio::{Cancellable, prelude::CancellableExt},
     glib,
     prelude::{AdwDialogExt, AlertDialogExt, PreferencesGroupExt, PreferencesPageExt},
 };
 use std::{
-    cell::RefCell,
-    cmp::Reverse,
+    cell::{Cell, RefCell},
     collections::HashMap,
     fs,
     rc::Rc,
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
+use theme_icon_group::ThemeIconGroup;
 use tracing::{debug, error};
 
+pub struct Icon {
+    pub pixbuf: Pixbuf,
+    pub source: IconSource,
+    /// Decoded image format, e.g. `png` or `svg`
+    pub format: Option<String>,
+    /// Original data of svg images, installed as the scalable icon
+    pub svg: Option<Vec<u8>>,
+}
+impl Icon {
+    /// Larger images are scaled down to this size. Only scalable loaders like svg decode at
+    /// this size, the others decode at full size first so the pixel budget bounds those.
+    const MAX_DECODE_SIZE: i32 = 1024;
+
+    /// Checks the header before decoding, the error is the reason the image is refused
+    pub fn from_bytes(bytes: &[u8], source: IconSource) -> Result<Self> {
+        let header = ImageHeader::peek(bytes)?;
+
+        // Frames of an icon are checked one by one, a frame that is too large is skipped
+        let (pixbuf, format) = if header.format == ImageFormat::Ico {
+            Self::decode_largest_ico_frame(bytes)?
+        } else {
+            header.check_pixel_budget()?;
+            Self::decode(bytes)?
+        };
+
+        let svg = if header.format == ImageFormat::Svg {
+            Some(bytes.to_vec())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            pixbuf,
+            source,
+            format,
+            svg,
+        })
+    }
+
+    fn decode(bytes: &[u8]) -> Result<(Pixbuf, Option<String>)> {
+        let loader = PixbufLoader::new();
+        loader.connect_size_prepared(|loader, width, height| {
+            let longest = width.max(height);
+            if longest <= Self::MAX_DECODE_SIZE {
+                return;
+            }
+            let scale = |size: i32| {
+                let scaled =
+                    i64::from(size) * i64::from(Self::MAX_DECODE_SIZE) / i64::from(longest);
+                i32::try_from(scaled).unwrap_or(1).max(1)
+            };
+            debug!(width, height, "Scaling image down");
+            loader.set_size(scale(width), scale(height));
+        });
+        loader.write(bytes)?;
+        loader.close()?;
+
+        let pixbuf = loader.pixbuf().context("No image in data")?;
+        let format = loader
+            .format()
+            .and_then(|format| format.name())
+            .map(|name| name.to_string());
+
+        Ok((pixbuf, format))
+    }
+
+    /// Decodes every frame, broken ones and ones that are too large are left out
+    fn decode_largest_ico_frame(bytes: &[u8]) -> Result<(Pixbuf, Option<String>)> {
+        let pixbuf = image_header::get_ico_frames(bytes)?
+            .iter()
+            .filter_map(|frame| {
+                let result = frame.check_pixel_budget().and_then(|()| {
+                    if frame.is_png() {
+                        Self::decode(frame.data)
+                    } else {
+                        Self::decode(&frame.to_ico())
+                    }
+                });
+                match result {
+                    Ok((pixbuf, _)) => Some(pixbuf),
+                    Err(error) => {
+                        debug!(
+                            width = frame.width,
+                            height = frame.height,
+                            "Skipped icon frame: {error:?}"
+                        );
+                        None
+                    }
+                }
+            })
+            .max_by_key(|pixbuf| pixbuf.width() * pixbuf.height())
+            .context("No frame of the icon could be decoded")?;
+
+        Ok((pixbuf, Some("ico".to_string())))
+    }
+
+    /// Higher is better: not generated, not monochrome, non-maskable, square, largest,
+    /// then source and format.
+    /// An svg scales to any size, so it counts as the largest.
+    fn get_rank(&self) -> (bool, bool, bool, bool, i32, u8, u8) {
+        let width = self.pixbuf.width();
+        let height = self.pixbuf.height();
+        let is_svg = self.format.as_deref() == Some("svg");
+        let size = if is_svg { i32::MAX } else { width.min(height) };
+        let format_rank = match self.format.as_deref() {
+            Some("svg") => 3,
+            Some("png") => 2,
+            Some("ico") => 0,
+            _ => 1,
+        };
+
+        (
+            self.source != IconSource::Monogram,
+            !self.source.is_monochrome(),
+            !self.source.is_maskable(),
+            width == height,
+            size,
+            self.source.get_priority(),
+            format_rank,
+        )
+    }
+}
+
 pub struct IconPicker {
     init: RefCell<bool>,
     fetched_icons_ts: RefCell<SystemTime>,
@@ -37,11 +168,22 @@ pub struct IconPicker {
     desktop_file: Rc<RefCell<DesktopFile>>,
     icons: Rc<RefCell<HashMap<String, Rc<Icon>>>>,
     icons_ordered: RefCell<Vec<(String, Rc<Icon>)>>,
+    manifest: RefCell<Option<WebAppManifest>>,
+    /// Of the icon loading that is shown in the dialog
+    cancellable: RefCell<Cancellable>,
+    /// Stays selected while more icons come in
+    picked_icon_url: RefCell<Option<String>>,
+    /// Of the last online icon search, reset when it is done
+    skipped_icons: Cell<usize>,
+    pref_group_icons: PreferencesGroup,
     pref_row_icons: PreferencesRow,
     pref_row_icons_fail: PreferencesRow,
     pref_row_icons_flow_box: RefCell<Option<FlowBox>>,
     pref_group_icons_reset_button: Button,
     pref_group_icons_add_button_row: ButtonRow,
+    icon_editor: Rc<IconEditor>,
+    theme_icon_group: Rc<ThemeIconGroup>,
+    certificate_group: Rc<CertificateGroup>,
     content_box: gtk::Box,
     spinner: Spinner,
 }
@@ -50,6 +192,8 @@ impl IconPicker {
     pub const DIALOG_CANCEL: &str = "cancel";
     /// In seconds
     pub const ONLINE_FETCH_THROTTLE: u64 = 20;
+    /// Key of the generated icon, urls and paths are used for the others
+    const MONOGRAM_ICON_NAME: &str = "monogram";
 
     pub fn new(app: &Rc<App>, desktop_file: &Rc<RefCell<DesktopFile>>) -> Rc<Self> {
         let icons = Rc::new(RefCell::new(HashMap::new()));
@@ -61,8 +205,14 @@ impl IconPicker {
         let pref_row_icons_fail = Self::build_pref_row_icons_fail();
         let (pref_group_icons, pref_group_icons_reset_button) = Self::build_pref_group_icons();
         let pref_group_icons_add_button_row = Self::build_pref_row_add_icon();
+        let icon_editor = IconEditor::new();
+        let theme_icon_group = ThemeIconGroup::new(app);
+        let certificate_group = CertificateGroup::new(app);
 
+        prefs_page.add(certificate_group.get_pref_group());
         prefs_page.add(&pref_group_icons);
+        prefs_page.add(icon_editor.get_pref_group());
+        prefs_page.add(theme_icon_group.get_pref_group());
         pref_group_icons.add(&pref_row_icons);
         pref_group_icons.add(&pref_row_icons_fail);
         pref_group_icons.add(&pref_group_icons_add_button_row);
@@ -84,11 +234,19 @@ impl IconPicker {
             desktop_file: desktop_file.clone(),
             icons,
             icons_ordered,
+            manifest: RefCell::new(None),
+            cancellable: RefCell::new(Cancellable::new()),
+            picked_icon_url: RefCell::new(None),
+            skipped_icons: Cell::new(0),
+            pref_group_icons,
             pref_row_icons,
             pref_row_icons_fail,
             pref_row_icons_flow_box: RefCell::new(None),
             pref_group_icons_reset_button,
             pref_group_icons_add_button_row,
+            icon_editor,
+            theme_icon_group,
+            certificate_group,
             content_box,
             spinner,
         })
@@ -96,12 +254,30 @@ impl IconPicker {
 
     pub fn init(self: &Rc<Self>) {
         let mut is_init = self.init.borrow_mut();
+        self.icon_editor
+            .set_browser(self.desktop_file.borrow().get_browser().as_deref());
         self.load_icons(false);
 
         if *is_init {
             return;
         }
 
+        self.icon_editor.init();
+
+        // Replaces the selected icon, the icon editor does not apply to it
+        let self_clone = self.clone();
+        self.theme_icon_group.init(move || {
+            *self_clone.picked_icon_url.borrow_mut() = None;
+            if let Some(flow_box) = self_clone.pref_row_icons_flow_box.borrow().as_ref() {
+                flow_box.unselect_all();
+            }
+        });
+
+        let self_clone = self.clone();
+        self.certificate_group.init(move || {
+            self_clone.load_icons(true);
+        });
+
         let self_clone = self.clone();
         self.pref_group_icons_reset_button
             .connect_clicked(move |_| {
@@ -124,7 +300,7 @@ impl IconPicker {
     ) -> AlertDialog
     where
         Success: Fn() + 'static,
-        Fail: Fn() + 'static,
+        Fail: Fn(&anyhow::Error) + 'static,
     {
         self.init();
 
@@ -143,8 +319,12 @@ impl IconPicker {
         dialog.connect_response(
             Some(Self::DIALOG_SAVE),
             move |_, _| match (|| -> Result<()> {
-                let icon = self_clone.get_selected_icon()?;
-                self_clone.save(&icon)?;
+                if let Some(icon_name) = self_clone.theme_icon_group.get_selected() {
+                    self_clone.save_themed(&icon_name)?;
+                } else {
+                    let icon = self_clone.icon_editor.get_edited_icon()?;
+                    self_clone.save(&icon)?;
+                }
                 Ok(())
             })() {
                 Ok(()) => {
@@ -155,18 +335,27 @@ impl IconPicker {
                 Err(error) => {
                     error!("Error saving icon: {error:?}");
                     if let Some(fail_cb) = &fail_cb {
-                        fail_cb();
+                        fail_cb(&error);
                     }
                 }
             },
         );
 
+        let self_clone = self.clone();
+        dialog.connect_closed(move |_| {
+            self_clone.cancellable.borrow().cancel();
+        });
+
         dialog.present(Some(&self.app.window.adw_window));
         dialog
     }
 
+    /// The generated icon is saved when offline or when the site has no icons
     pub async fn save_first_icon_found(self: &Rc<Self>) -> Result<()> {
-        self.set_online_icons(false).await?;
+        self.add_monogram_icon();
+        if let Err(error) = self.set_online_icons(false, &Cancellable::new()).await {
+            error!("{error:?}");
+        }
         let icons_ordered_borrow = self.icons_ordered.borrow();
 
         let Some((_url, icon)) = icons_ordered_borrow.first() else {
@@ -177,6 +366,11 @@ impl IconPicker {
         Ok(())
     }
 
+    /// Manifest found by the last online icon search
+    pub fn get_manifest(&self) -> Option<WebAppManifest> {
+        self.manifest.borrow().clone()
+    }
+
     fn get_selected_icon(self: &Rc<Self>) -> Result<Rc<Icon>> {
         let url_or_path = self
             .clone()
@@ -202,6 +396,7 @@ impl IconPicker {
     }
 
     fn set_icons_loading(&self) {
+        self.icon_editor.set_icon(None);
         self.prefs_page.set_visible(false);
         self.spinner.set_visible(true);
         self.pref_row_icons.set_visible(false);
@@ -209,6 +404,7 @@ impl IconPicker {
     }
 
     fn set_no_icons(&self) {
+        self.icon_editor.set_icon(None);
         self.prefs_page.set_visible(true);
         self.spinner.set_visible(false);
         self.pref_row_icons.set_visible(false);
@@ -240,16 +436,31 @@ impl IconPicker {
     }
 
     fn load_icons(self: &Rc<Self>, force: bool) {
+        let cancellable = Cancellable::new();
+        self.cancellable.replace(cancellable.clone()).cancel();
+        *self.picked_icon_url.borrow_mut() = None;
+
         let self_clone = self.clone();
 
         glib::spawn_future_local(async move {
             self_clone.set_icons_loading();
+            self_clone.add_monogram_icon();
+            self_clone.reload_theme_icons();
 
-            if let Err(error) = self_clone.set_online_icons(force).await {
-                error!("{error:?}");
-                self_clone.set_no_icons();
+            let result = self_clone.set_online_icons(force, &cancellable).await;
+            if cancellable.is_cancelled() {
                 return;
             }
+            let url = self_clone.desktop_file.borrow().get_url();
+            self_clone.certificate_group.reload(url.as_deref());
+
+            // The generated icon is still shown
+            if let Err(error) = result {
+                error!("{error:?}");
+                self_clone
+                    .pref_group_icons
+                    .set_description(Some("No icons found online"));
+            }
 
             self_clone.reload_icons();
         });
@@ -274,11 +485,38 @@ impl IconPicker {
             let size_text = format!("{} x {}", icon.pixbuf.width(), icon.pixbuf.height());
             let label = Label::builder().label(&size_text).build();
             frame.append(&label);
+            let source_label = Label::builder()
+                .label(icon.source.get_label())
+                .css_classes(["caption", "dimmed"])
+                .build();
+            frame.append(&source_label);
 
             flow_box.insert(&frame, -1);
         }
 
-        if let Some(first_child) = flow_box.first_child() {
+        let self_clone_selected = self.clone();
+        flow_box.connect_selected_children_changed(move |_| {
+            let icon = self_clone_selected.get_selected_icon().ok();
+            self_clone_selected.icon_editor.set_icon(icon.as_ref());
+        });
+
+        // Only by the user, not when selecting from code
+        let self_clone_activated = self.clone();
+        flow_box.connect_child_activated(move |_, flow_box_child| {
+            *self_clone_activated.picked_icon_url.borrow_mut() = flow_box_child
+                .child()
+                .map(|widget| widget.widget_name().to_string());
+            self_clone_activated.theme_icon_group.unselect();
+        });
+
+        *self_clone.pref_row_icons_flow_box.borrow_mut() = Some(flow_box.clone());
+
+        let picked_icon_url = self.picked_icon_url.borrow().clone();
+        if self.theme_icon_group.get_selected().is_some() {
+            // Nothing selected here, the theme icon is saved
+        } else if let Some(picked_icon_url) = picked_icon_url {
+            self.select_icon(&picked_icon_url);
+        } else if let Some(first_child) = flow_box.first_child() {
             let flow_box_child = first_child.downcast_ref::<FlowBoxChild>();
             if let Some(flow_box_child) = flow_box_child {
                 flow_box.select_child(flow_box_child);
@@ -290,11 +528,13 @@ impl IconPicker {
         } else {
             self.set_show_icons();
         }
-
-        *self_clone.pref_row_icons_flow_box.borrow_mut() = Some(flow_box);
     }
 
-    async fn set_online_icons(self: &Rc<Self>, force: bool) -> Result<()> {
+    async fn set_online_icons(
+        self: &Rc<Self>,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<()> {
         if !force && self.should_throttle() {
             return Ok(());
         }
@@ -304,30 +544,108 @@ impl IconPicker {
         let Some(url) = self.desktop_file.borrow().get_url() else {
             bail!("No url on desktop file")
         };
-        let Ok(mut icon_fetcher) = IconFetcher::new(&self.app, &url) else {
+        let Ok(mut icon_fetcher) = IconFetcher::new(&self.app, &url, force, cancellable) else {
             bail!("Invalid url")
         };
-        let Ok(icons) = icon_fetcher.get_online_icons().await else {
+        let icon_urls_result = icon_fetcher.fetch_icon_urls().await;
+        if cancellable.is_cancelled() {
+            // Not throttled, so the next time the picker opens it fetches again
+            *self.fetched_icons_ts.borrow_mut() = UNIX_EPOCH;
+            bail!("Fetching online icons cancelled")
+        }
+        if icon_urls_result.is_err() {
             bail!("Failed to get online icons")
-        };
-
-        let mut self_icons_borrow = self.icons.borrow_mut();
-        let mut self_icons_ordered_borrow = self.icons_ordered.borrow_mut();
-
-        for (url, icon) in icons {
-            self_icons_borrow.insert(url, icon);
         }
+        *self.manifest.borrow_mut() = icon_fetcher.get_manifest().cloned();
+        self.icon_editor.set_default_color(
+            icon_fetcher
+                .get_manifest()
+                .and_then(|manifest| manifest.theme_color.as_deref())
+                .and_then(|theme_color| RGBA::parse(theme_color).ok()),
+        );
 
-        *self_icons_ordered_borrow = self_icons_borrow.clone().into_iter().collect();
-        self_icons_ordered_borrow.sort_by_key(|(_, a)| Reverse(a.pixbuf.byte_length()));
+        let self_clone = self.clone();
+        icon_fetcher
+            .fetch_icons(move |progress| self_clone.add_online_icon(progress))
+            .await;
+        let skipped = self.skipped_icons.take();
+        let description = (skipped > 0).then(|| format!("{skipped} skipped, see the log"));
+        self.pref_group_icons
+            .set_description(description.as_deref());
+        if cancellable.is_cancelled() {
+            *self.fetched_icons_ts.borrow_mut() = UNIX_EPOCH;
+            bail!("Fetching online icons cancelled")
+        }
 
-        if self_icons_borrow.is_empty() {
+        if self
+            .icons
+            .borrow()
+            .values()
+            .all(|icon| icon.source == IconSource::Monogram)
+        {
             bail!("No icons found for: {url}")
         }
 
         Ok(())
     }
 
+    /// Shows the icons while the others are still loading
+    fn add_online_icon(self: &Rc<Self>, progress: IconProgress) {
+        let IconProgress {
+            icon,
+            done,
+            total,
+            skipped,
+        } = progress;
+        let description = match skipped {
+            0 => format!("Loaded {done} of {total}"),
+            _ => format!("Loaded {done} of {total}, {skipped} skipped"),
+        };
+        self.pref_group_icons.set_description(Some(&description));
+        self.skipped_icons.replace(skipped);
+
+        let Some((url, icon)) = icon else {
+            return;
+        };
+        self.icons.borrow_mut().insert(url, icon);
+        self.sort_icons();
+
+        self.reload_icons();
+    }
+
+    /// Always a candidate, it works offline
+    fn add_monogram_icon(&self) {
+        let svg = {
+            let desktop_file_borrow = self.desktop_file.borrow();
+            monogram::build_monogram(
+                desktop_file_borrow.get_name().as_deref(),
+                desktop_file_borrow.get_url().as_deref(),
+            )
+        };
+        let icon = match Icon::from_bytes(&svg, IconSource::Monogram) {
+            Ok(icon) => Rc::new(icon),
+            Err(error) => {
+                error!("Failed to generate icon: {error:?}");
+                return;
+            }
+        };
+        self.icons
+            .borrow_mut()
+            .insert(Self::MONOGRAM_ICON_NAME.to_string(), icon);
+        self.sort_icons();
+    }
+
+    fn sort_icons(&self) {
+        let mut icons_ordered: Vec<_> = self.icons.borrow().clone().into_iter().collect();
+        icons_ordered.sort_by(|(url_a, icon_a), (url_b, icon_b)| {
+            icon_b
+                .get_rank()
+                .cmp(&icon_a.get_rank())
+                .then_with(|| url_a.cmp(url_b))
+        });
+        *self.icons_ordered.borrow_mut() = icons_ordered;
+    }
+
     fn should_throttle(self: &Rc<Self>) -> bool {
         let now = SystemTime::now();
         let throttle_duration = Duration::from_secs(Self::ONLINE_FETCH_THROTTLE);
@@ -375,15 +693,16 @@ impl IconPicker {
                 let filename = file.parse_name().to_string();
                 debug!("Loading image: '{filename}'");
 
-                let pixbuf = match Pixbuf::from_file(&path) {
+                let icon = match fs::read(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|bytes| Icon::from_bytes(&bytes, IconSource::File))
+                {
                     Err(error) => {
                         error!("Could not load image into a Pixbuf: '{error:?}'");
                         return;
                     }
-                    Ok(pixbuf) => pixbuf,
+                    Ok(icon) => Rc::new(icon),
                 };
-
-                let icon = Rc::new(Icon { pixbuf });
                 self_clone
                     .icons
                     .borrow_mut()
@@ -399,36 +718,26 @@ impl IconPicker {
         );
     }
 
-    fn save(self: &Rc<Self>, icon: &Rc<Icon>) -> Result<()> {
-        let mut desktop_file_borrow = self.desktop_file.borrow_mut();
-        if let Some(old_icon_path) = desktop_file_borrow.get_icon_path()
-            && old_icon_path.is_file()
-        {
-            fs::remove_file(old_icon_path).context("Failed to remove old icon")?;
-        }
-
-        let app_id = desktop_file_borrow
-            .get_id()
-            .context("No file id on DesktopFile")?;
-
-        let icon_dir = self.app.dirs.icons();
-        let file_name = sanitize_filename::sanitize(format!("{app_id}.png"));
-        let save_path = icon_dir.join(&file_name);
-
-        debug!(
-            "Saving icon '{}' to fs: {}",
-            &file_name,
-            save_path.display()
+    fn reload_theme_icons(&self) {
+        let desktop_file_borrow = self.desktop_file.borrow();
+        self.theme_icon_group.reload(
+            desktop_file_borrow.get_name().as_deref(),
+            desktop_file_borrow.get_url().as_deref(),
         );
+    }
 
-        icon.pixbuf
-            .savev(save_path.clone(), "png", &[])
-            .context("Failed to save icon to fs")?;
-
-        desktop_file_borrow.set_icon_path(&save_path);
-        drop(desktop_file_borrow);
+    fn save_themed(&self, icon_name: &str) -> Result<()> {
+        self.desktop_file
+            .borrow_mut()
+            .set_themed_icon(icon_name)
+            .context("Failed to set themed icon")
+    }
 
-        Ok(())
+    fn save(self: &Rc<Self>, icon: &Rc<Icon>) -> Result<()> {
+        self.desktop_file
+            .borrow_mut()
+            .install_icon(&icon.pixbuf, icon.svg.as_deref())
+            .context("Failed to save icon to fs")
     }
 
     fn build_spinner() -> Spinner {
@@ -0,0 +1,158 @@
+use crate::application::App;
+use gtk::{Align, Button, prelude::ButtonExt};
+use libadwaita::{
+    ActionRow, AlertDialog, PreferencesGroup, ResponseAppearance,
+    prelude::{
+        ActionRowExt, AdwDialogExt, AlertDialogExt, PreferencesGroupExt, PreferencesRowExt,
+        WidgetExt,
+    },
+};
+use std::{cell::RefCell, rc::Rc};
+use tracing::error;
+use url::Url;
+
+/// Shows the certificate of a host that failed verification or that the user trusted
+pub struct CertificateGroup {
+    app: Rc<App>,
+    pref_group: PreferencesGroup,
+    row: ActionRow,
+    trust_button: Button,
+    remove_button: Button,
+    host: RefCell<Option<String>>,
+    on_change: RefCell<Option<Box<dyn Fn()>>>,
+}
+impl CertificateGroup {
+    pub const DIALOG_TRUST: &str = "trust";
+    pub const DIALOG_CANCEL: &str = "cancel";
+
+    pub fn new(app: &Rc<App>) -> Rc<Self> {
+        let row = ActionRow::builder()
+            .subtitle_selectable(true)
+            .css_classes(["property"])
+            .build();
+        let trust_button = Button::builder()
+            .label("Trust…")
+            .valign(Align::Center)
+            .build();
+        let remove_button = Button::builder()
+            .label("Remove")
+            .valign(Align::Center)
+            .css_classes(["destructive-action"])
+            .build();
+        row.add_suffix(&trust_button);
+        row.add_suffix(&remove_button);
+
+        let pref_group = PreferencesGroup::builder().title("Certificate").build();
+        pref_group.add(&row);
+        pref_group.set_visible(false);
+
+        Rc::new(Self {
+            app: app.clone(),
+            pref_group,
+            row,
+            trust_button,
+            remove_button,
+            host: RefCell::new(None),
+            on_change: RefCell::new(None),
+        })
+    }
+
+    pub fn init<OnChange>(self: &Rc<Self>, on_change: OnChange)
+    where
+        OnChange: Fn() + 'static,
+    {
+        *self.on_change.borrow_mut() = Some(Box::new(on_change));
+
+        let self_clone = self.clone();
+        self.trust_button.connect_clicked(move |_| {
+            self_clone.show_trust_dialog();
+        });
+
+        let self_clone = self.clone();
+        self.remove_button.connect_clicked(move |_| {
+            let Some(host) = self_clone.host.borrow().clone() else {
+                return;
+            };
+            if let Err(error) = self_clone.app.fetch.remove_trusted_certificate(&host) {
+                error!("{error:?}");
+                self_clone.app.show_error(&error);
+                return;
+            }
+            self_clone.changed();
+        });
+    }
+
+    pub fn get_pref_group(&self) -> &PreferencesGroup {
+        &self.pref_group
+    }
+
+    pub fn reload(&self, url: Option<&str>) {
+        let host = url
+            .and_then(|url| Url::parse(url).ok())
+            .filter(|url| url.scheme() == "https")
+            .and_then(|url| url.host_str().map(str::to_string));
+        self.host.borrow_mut().clone_from(&host);
+
+        let Some(host) = host else {
+            self.pref_group.set_visible(false);
+            return;
+        };
+
+        if let Some(fingerprint) = self.app.fetch.get_trusted_certificate(&host) {
+            PreferencesRowExt::set_title(&self.row, &format!("Trusted for {host}"));
+            self.row.set_subtitle(&fingerprint);
+            self.trust_button.set_visible(false);
+            self.remove_button.set_visible(true);
+            self.pref_group.set_visible(true);
+        } else if let Some(fingerprint) = self.app.fetch.get_rejected_certificate(&host) {
+            PreferencesRowExt::set_title(&self.row, &format!("Not trusted for {host}"));
+            self.row.set_subtitle(&fingerprint);
+            self.trust_button.set_visible(true);
+            self.remove_button.set_visible(false);
+            self.pref_group.set_visible(true);
+        } else {
+            self.pref_group.set_visible(false);
+        }
+    }
+
+    fn show_trust_dialog(self: &Rc<Self>) {
+        let Some(host) = self.host.borrow().clone() else {
+            return;
+        };
+        let Some(fingerprint) = self.app.fetch.get_rejected_certificate(&host) else {
+            return;
+        };
+
+        let dialog = AlertDialog::builder()
+            .heading(format!("Trust the certificate of {host}?"))
+            .body(format!(
+                "Only do this when the SHA-256 fingerprint below is the one of your server, \
+                e.g. as shown by your administrator.\n\n{fingerprint}"
+            ))
+            .width_request(400)
+            .build();
+        dialog.add_response(Self::DIALOG_CANCEL, "_Cancel");
+        dialog.add_response(Self::DIALOG_TRUST, "_Trust");
+        dialog.set_response_appearance(Self::DIALOG_TRUST, ResponseAppearance::Destructive);
+        dialog.set_default_response(Some(Self::DIALOG_CANCEL));
+        dialog.set_close_response(Self::DIALOG_CANCEL);
+
+        let self_clone = self.clone();
+        dialog.connect_response(Some(Self::DIALOG_TRUST), move |_, _| {
+            if let Err(error) = self_clone.app.fetch.trust_certificate(&host, &fingerprint) {
+                error!("{error:?}");
+                self_clone.app.show_error(&error);
+                return;
+            }
+            self_clone.changed();
+        });
+
+        dialog.present(Some(&self.app.window.adw_window));
+    }
+
+    fn changed(&self) {
+        if let Some(on_change) = self.on_change.borrow().as_ref() {
+            on_change();
+        }
+    }
+}
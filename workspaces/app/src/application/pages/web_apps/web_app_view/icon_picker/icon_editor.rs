@@ -0,0 +1,419 @@
+use super::Icon;
+use crate::application::icons::BrowserIcon;
+use anyhow::{Context, Result};
+use common::browsers::Browser;
+use gtk::{
+    Align, ColorDialog, ColorDialogButton, ContentFit, Picture, StringList,
+    cairo::{self, Format, ImageSurface},
+    gdk::{self, RGBA, prelude::GdkCairoContextExt},
+    gdk_pixbuf::Pixbuf,
+    prelude::WidgetExt,
+};
+use libadwaita::{
+    ComboRow, PreferencesGroup, PreferencesRow, SpinRow, SwitchRow,
+    prelude::{ActionRowExt, ComboRowExt, PreferencesGroupExt, PreferencesRowExt},
+};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    f64::consts::{FRAC_PI_2, PI},
+    rc::Rc,
+};
+use tracing::debug;
+
+/// Size of the edited icon, the largest hicolor size
+const RENDER_SIZE: i32 = 512;
+const PREVIEW_SIZE: i32 = 128;
+/// Maskable icons keep their content in a centered circle of 80%
+/// <https://www.w3.org/TR/appmanifest/#icon-masks>
+const MASKABLE_SAFE_ZONE: f64 = 0.8;
+/// Empty space around masked icons, like GNOME app icons have
+const MASK_MARGIN: f64 = 1.0 / 16.0;
+const MASK_RADIUS: f64 = 0.15;
+const BADGE_SIZE: f64 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconMask {
+    None,
+    RoundedSquare,
+    Circle,
+}
+impl IconMask {
+    const ALL: [Self; 3] = [Self::None, Self::RoundedSquare, Self::Circle];
+
+    fn get_label(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::RoundedSquare => "Rounded square",
+            Self::Circle => "Circle",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IconEdit {
+    /// Fraction of the icon size on each side
+    pub padding: f64,
+    pub background: Option<RGBA>,
+    pub mask: IconMask,
+    pub badge: bool,
+}
+impl IconEdit {
+    /// Maskable icons need a mask, other icons are left as they are
+    fn default_for(icon: &Icon) -> Self {
+        Self {
+            padding: 0.0,
+            background: None,
+            mask: if icon.source.is_maskable() {
+                IconMask::RoundedSquare
+            } else {
+                IconMask::None
+            },
+            badge: false,
+        }
+    }
+
+    fn is_unchanged(&self) -> bool {
+        self.padding == 0.0
+            && self.background.is_none()
+            && self.mask == IconMask::None
+            && !self.badge
+    }
+}
+
+pub struct IconEditor {
+    pref_group: PreferencesGroup,
+    preview: Picture,
+    padding_row: SpinRow,
+    background_row: SwitchRow,
+    color_button: ColorDialogButton,
+    mask_row: ComboRow,
+    badge_row: SwitchRow,
+    icon: RefCell<Option<Rc<Icon>>>,
+    /// Icon of the browser the web app uses
+    badge: RefCell<Option<Pixbuf>>,
+    /// Colour to start with when the background is enabled, e.g. the manifest `theme_color`
+    default_color: RefCell<Option<RGBA>>,
+    is_loading: RefCell<bool>,
+}
+impl IconEditor {
+    pub fn new() -> Rc<Self> {
+        let preview = Picture::builder()
+            .width_request(PREVIEW_SIZE)
+            .height_request(PREVIEW_SIZE)
+            .content_fit(ContentFit::ScaleDown)
+            .halign(Align::Center)
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+        let preview_row = PreferencesRow::builder()
+            .child(&preview)
+            .activatable(false)
+            .build();
+
+        let padding_row = SpinRow::with_range(0.0, 30.0, 1.0);
+        padding_row.set_title("Padding");
+        padding_row.set_subtitle("Percent of the icon size");
+
+        let color_button = ColorDialogButton::builder()
+            .dialog(&ColorDialog::builder().with_alpha(false).build())
+            .valign(Align::Center)
+            .build();
+        let background_row = SwitchRow::builder().title("Background").build();
+        background_row.add_suffix(&color_button);
+
+        let mask_labels = IconMask::ALL.map(IconMask::get_label);
+        let mask_row = ComboRow::builder()
+            .title("Shape")
+            .model(&StringList::new(&mask_labels))
+            .build();
+
+        let badge_row = SwitchRow::builder()
+            .title("Browser badge")
+            .subtitle("Tell apart the same site in different browsers")
+            .sensitive(false)
+            .build();
+
+        let pref_group = PreferencesGroup::builder().title("Edit").build();
+        pref_group.add(&preview_row);
+        pref_group.add(&padding_row);
+        pref_group.add(&background_row);
+        pref_group.add(&mask_row);
+        pref_group.add(&badge_row);
+        pref_group.set_visible(false);
+
+        Rc::new(Self {
+            pref_group,
+            preview,
+            padding_row,
+            background_row,
+            color_button,
+            mask_row,
+            badge_row,
+            icon: RefCell::new(None),
+            badge: RefCell::new(None),
+            default_color: RefCell::new(None),
+            is_loading: RefCell::new(false),
+        })
+    }
+
+    pub fn init(self: &Rc<Self>) {
+        let self_clone = self.clone();
+        self.padding_row
+            .connect_value_notify(move |_| self_clone.reload_preview());
+
+        let self_clone = self.clone();
+        self.background_row
+            .connect_active_notify(move |_| self_clone.reload_preview());
+
+        let self_clone = self.clone();
+        self.color_button.connect_rgba_notify(move |_| {
+            self_clone.background_row.set_active(true);
+            self_clone.reload_preview();
+        });
+
+        let self_clone = self.clone();
+        self.mask_row
+            .connect_selected_notify(move |_| self_clone.reload_preview());
+
+        let self_clone = self.clone();
+        self.badge_row
+            .connect_active_notify(move |_| self_clone.reload_preview());
+    }
+
+    pub fn get_pref_group(&self) -> &PreferencesGroup {
+        &self.pref_group
+    }
+
+    /// The browser can change between edits, so set it every time the icon picker opens
+    pub fn set_browser(&self, browser: Option<&Browser>) {
+        let badge = browser.and_then(|browser| browser.get_icon_pixbuf(RENDER_SIZE / 2));
+        self.badge_row.set_sensitive(badge.is_some());
+        *self.badge.borrow_mut() = badge;
+        self.reload_preview();
+    }
+
+    pub fn set_default_color(&self, color: Option<RGBA>) {
+        *self.default_color.borrow_mut() = color;
+    }
+
+    /// Start editing an icon with the default settings for it
+    /// Selecting the same icon again keeps the edits
+    pub fn set_icon(self: &Rc<Self>, icon: Option<&Rc<Icon>>) {
+        if let (Some(current), Some(icon)) = (self.icon.borrow().as_ref(), icon)
+            && Rc::ptr_eq(current, icon)
+        {
+            return;
+        }
+        *self.icon.borrow_mut() = icon.cloned();
+        self.pref_group.set_visible(icon.is_some());
+
+        let Some(icon) = icon else {
+            return;
+        };
+
+        let edit = IconEdit::default_for(icon);
+        let color = self
+            .default_color
+            .borrow()
+            .unwrap_or_else(|| get_dominant_color(&icon.pixbuf));
+
+        *self.is_loading.borrow_mut() = true;
+        self.padding_row.set_value(edit.padding * 100.0);
+        self.color_button.set_rgba(&color);
+        self.background_row.set_active(edit.background.is_some());
+        self.mask_row.set_selected(
+            IconMask::ALL
+                .iter()
+                .position(|mask| *mask == edit.mask)
+                .and_then(|index| u32::try_from(index).ok())
+                .unwrap_or(0),
+        );
+        self.badge_row.set_active(edit.badge);
+        *self.is_loading.borrow_mut() = false;
+
+        self.reload_preview();
+    }
+
+    /// The selected icon with the edits applied, an unchanged icon keeps its svg
+    pub fn get_edited_icon(&self) -> Result<Rc<Icon>> {
+        let icon = self.icon.borrow().clone().context("No icon to edit")?;
+        let edit = self.get_edit();
+
+        if edit.is_unchanged() {
+            return Ok(icon);
+        }
+        debug!(?edit, "Applying icon edit");
+
+        let pixbuf = render(&icon, &edit, self.badge.borrow().as_ref(), RENDER_SIZE)?;
+        Ok(Rc::new(Icon {
+            pixbuf,
+            source: icon.source.clone(),
+            format: Some("png".to_string()),
+            svg: None,
+        }))
+    }
+
+    fn get_edit(&self) -> IconEdit {
+        IconEdit {
+            padding: self.padding_row.value() / 100.0,
+            background: self
+                .background_row
+                .is_active()
+                .then(|| self.color_button.rgba()),
+            mask: usize::try_from(self.mask_row.selected())
+                .ok()
+                .and_then(|index| IconMask::ALL.get(index).copied())
+                .unwrap_or(IconMask::None),
+            badge: self.badge_row.is_active() && self.badge.borrow().is_some(),
+        }
+    }
+
+    fn reload_preview(&self) {
+        if *self.is_loading.borrow() {
+            return;
+        }
+        let Some(icon) = self.icon.borrow().clone() else {
+            return;
+        };
+
+        match render(
+            &icon,
+            &self.get_edit(),
+            self.badge.borrow().as_ref(),
+            PREVIEW_SIZE,
+        ) {
+            Ok(pixbuf) => self.preview.set_pixbuf(Some(&pixbuf)),
+            Err(error) => debug!("Failed to render icon preview: {error:?}"),
+        }
+    }
+}
+
+fn render(icon: &Icon, edit: &IconEdit, badge: Option<&Pixbuf>, size: i32) -> Result<Pixbuf> {
+    let surface = ImageSurface::create(Format::ARgb32, size, size)?;
+    let context = cairo::Context::new(&surface)?;
+    let size_f = f64::from(size);
+
+    // Area of the shape
+    let margin = if edit.mask == IconMask::None {
+        0.0
+    } else {
+        size_f * MASK_MARGIN
+    };
+    let shape_size = size_f - margin * 2.0;
+
+    context.save()?;
+    match edit.mask {
+        IconMask::None => context.rectangle(0.0, 0.0, size_f, size_f),
+        IconMask::RoundedSquare => {
+            rounded_rectangle(&context, margin, shape_size, shape_size * MASK_RADIUS);
+        }
+        IconMask::Circle => {
+            context.arc(size_f / 2.0, size_f / 2.0, shape_size / 2.0, 0.0, 2.0 * PI);
+        }
+    }
+    context.clip();
+
+    if let Some(background) = &edit.background {
+        context.set_source_rgba(
+            f64::from(background.red()),
+            f64::from(background.green()),
+            f64::from(background.blue()),
+            f64::from(background.alpha()),
+        );
+        context.paint()?;
+    }
+
+    // Area the icon content has to fit in
+    let content_size = shape_size * (1.0 - edit.padding * 2.0);
+    let width = f64::from(icon.pixbuf.width());
+    let height = f64::from(icon.pixbuf.height());
+    let scale = if icon.source.is_maskable() {
+        // Only the safe zone has to fit, the rest is bleed that the mask cuts off
+        content_size / (width.min(height) * MASKABLE_SAFE_ZONE)
+    } else {
+        content_size / width.max(height)
+    };
+    draw_pixbuf_centered(&context, &icon.pixbuf, size_f / 2.0, size_f / 2.0, scale)?;
+    context.restore()?;
+
+    if edit.badge
+        && let Some(badge) = badge
+    {
+        let badge_size = size_f * BADGE_SIZE;
+        let scale = badge_size / f64::from(badge.width().max(badge.height()));
+        let center = size_f - badge_size / 2.0;
+        draw_pixbuf_centered(&context, badge, center, center, scale)?;
+    }
+
+    drop(context);
+    surface.flush();
+    gdk::pixbuf_get_from_surface(&surface, 0, 0, size, size).context("Failed to render icon")
+}
+
+fn draw_pixbuf_centered(
+    context: &cairo::Context,
+    pixbuf: &Pixbuf,
+    center_x: f64,
+    center_y: f64,
+    scale: f64,
+) -> Result<()> {
+    let width = f64::from(pixbuf.width()) * scale;
+    let height = f64::from(pixbuf.height()) * scale;
+
+    context.save()?;
+    context.translate(center_x - width / 2.0, center_y - height / 2.0);
+    context.scale(scale, scale);
+    context.set_source_pixbuf(pixbuf, 0.0, 0.0);
+    context.source().set_filter(cairo::Filter::Best);
+    context.paint()?;
+    context.restore()?;
+
+    Ok(())
+}
+
+fn rounded_rectangle(context: &cairo::Context, offset: f64, size: f64, radius: f64) {
+    let start = offset;
+    let end = offset + size;
+
+    context.new_sub_path();
+    context.arc(end - radius, start + radius, radius, -FRAC_PI_2, 0.0);
+    context.arc(end - radius, end - radius, radius, 0.0, FRAC_PI_2);
+    context.arc(start + radius, end - radius, radius, FRAC_PI_2, PI);
+    context.arc(start + radius, start + radius, radius, PI, PI + FRAC_PI_2);
+    context.close_path();
+}
+
+/// Most common colour of the opaque pixels, in buckets of 4 bits per channel
+fn get_dominant_color(pixbuf: &Pixbuf) -> RGBA {
+    let fallback = RGBA::new(1.0, 1.0, 1.0, 1.0);
+    let pixels = pixbuf.read_pixel_bytes();
+    let channels = usize::try_from(pixbuf.n_channels()).unwrap_or(4);
+    let rowstride = usize::try_from(pixbuf.rowstride()).unwrap_or(0);
+    let width = usize::try_from(pixbuf.width()).unwrap_or(0);
+    let has_alpha = pixbuf.has_alpha();
+
+    // Bucket -> (count, summed channels)
+    let mut buckets: HashMap<(u8, u8, u8), (u32, [u32; 3])> = HashMap::new();
+    for row in pixels.chunks(rowstride.max(1)) {
+        for pixel in row.chunks_exact(channels).take(width) {
+            if has_alpha && pixel[3] < 128 {
+                continue;
+            }
+            let key = (pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4);
+            let bucket = buckets.entry(key).or_insert((0, [0; 3]));
+            bucket.0 += 1;
+            for (sum, channel) in bucket.1.iter_mut().zip(pixel) {
+                *sum += u32::from(*channel);
+            }
+        }
+    }
+
+    let Some((count, sums)) = buckets.into_values().max_by_key(|(count, _)| *count) else {
+        return fallback;
+    };
+    #[allow(clippy::cast_precision_loss)]
+    let channel = |sum: u32| (sum as f32 / count as f32) / 255.0;
+
+    RGBA::new(channel(sums[0]), channel(sums[1]), channel(sums[2]), 1.0)
+}
@@ -1,40 +1,128 @@
+use super::Icon;
 use crate::application::App;
 use anyhow::{Result, bail};
-use common::{desktop_file::Icon, url::UrlExt};
+use common::{manifest::WebAppManifest, url::UrlExt};
 use gtk::{
-    gdk_pixbuf::Pixbuf,
-    gio::{Cancellable, MemoryInputStream},
+    gio::{Cancellable, CancellableFuture, prelude::CancellableExt},
     glib::{self},
 };
 use scraper::{Html, Selector};
-use serde::Deserialize;
 use std::{
-    collections::{HashMap, HashSet},
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
     rc::Rc,
 };
 use tracing::{debug, error, info};
 use url::Url;
 
-#[derive(Deserialize)]
-struct ManifestIcon {
-    src: Option<String>,
+/// Where an icon candidate was found
+#[derive(Debug, Clone, PartialEq)]
+pub enum IconSource {
+    Manifest {
+        sizes: Option<String>,
+        purpose: Option<String>,
+        mime_type: Option<String>,
+    },
+    AppleTouchIcon,
+    MaskIcon,
+    /// `<link rel="icon">`
+    Link {
+        sizes: Option<String>,
+    },
+    /// The `favicon.ico` fallback
+    Favicon,
+    File,
+    /// Generated letter icon, for when nothing else is found
+    Monogram,
 }
-#[derive(Deserialize)]
-struct ManifestJson {
-    icons: Option<Vec<ManifestIcon>>,
+impl IconSource {
+    /// Higher is preferred when icons are otherwise equal
+    pub fn get_priority(&self) -> u8 {
+        match self {
+            Self::File => 5,
+            Self::Manifest { .. } => 4,
+            Self::AppleTouchIcon => 3,
+            Self::Link { .. } => 2,
+            Self::Favicon => 1,
+            Self::MaskIcon | Self::Monogram => 0,
+        }
+    }
+
+    /// Maskable icons have padding for a safe zone that a mask is meant to cut off
+    pub fn is_maskable(&self) -> bool {
+        self.has_purpose("maskable")
+    }
+
+    /// Monochrome icons are a single color silhouette, they look wrong as an app icon
+    pub fn is_monochrome(&self) -> bool {
+        match self {
+            Self::MaskIcon => true,
+            _ => self.has_purpose("monochrome") && !self.is_maskable(),
+        }
+    }
+
+    /// A manifest icon that is only meant for the given purpose and not for `any`
+    fn has_purpose(&self, wanted: &str) -> bool {
+        match self {
+            Self::Manifest {
+                purpose: Some(purpose),
+                ..
+            } => {
+                let mut purposes = purpose.split_whitespace();
+                purposes.clone().any(|purpose| purpose == wanted)
+                    && !purposes.any(|purpose| purpose == "any")
+            }
+            _ => false,
+        }
+    }
+
+    pub fn get_label(&self) -> String {
+        match self {
+            Self::Manifest { purpose, .. } => match purpose {
+                Some(purpose) if self.is_maskable() || self.is_monochrome() => {
+                    format!("Manifest ({purpose})")
+                }
+                _ => "Manifest".to_string(),
+            },
+            Self::AppleTouchIcon => "Apple touch icon".to_string(),
+            Self::MaskIcon => "Mask icon".to_string(),
+            Self::Link { .. } => "Page icon".to_string(),
+            Self::Favicon => "favicon.ico".to_string(),
+            Self::File => "File".to_string(),
+            Self::Monogram => "Generated".to_string(),
+        }
+    }
+}
+
+/// Reported for every icon request that is done
+pub struct IconProgress {
+    pub icon: Option<(String, Rc<Icon>)>,
+    pub done: usize,
+    pub total: usize,
+    /// Refused by the image checks, the reason is logged
+    pub skipped: usize,
 }
 
 pub struct IconFetcher {
     app: Rc<App>,
+    cancellable: Cancellable,
+    /// Revalidate cached responses even when they are fresh
+    force: bool,
     url: Url,
     base_url: Option<Url>,
-    icon_urls: HashSet<String>,
-    /// `HashMap<manifest_url_string, (manifest_url, base_url)>`
+    /// `url` after redirects
+    document_url: Option<Url>,
+    icon_urls: HashMap<String, IconSource>,
+    /// `HashMap<manifest_url_string, (manifest_url, document_url)>`
     manifest_urls: HashMap<String, (Url, Url)>,
+    manifest: Option<WebAppManifest>,
 }
 impl IconFetcher {
-    pub fn new(app: &Rc<App>, url: &str) -> Result<Self> {
-        let Some(url) = Url::parse(url).ok() else {
+    /// Sites often link the same icon in many sizes
+    const MAX_CONCURRENT_FETCHES: usize = 6;
+
+    pub fn new(app: &Rc<App>, url: &str, force: bool, cancellable: &Cancellable) -> Result<Self> {
+        let Some(url) = Url::parse(url).ok().filter(UrlExt::is_web_url) else {
             bail!("Invalid url")
         };
         let base_url = if url.has_path() {
@@ -45,15 +133,20 @@ impl IconFetcher {
 
         Ok(Self {
             app: app.clone(),
+            cancellable: cancellable.clone(),
+            force,
             url,
             base_url,
-            icon_urls: HashSet::new(),
+            document_url: None,
+            icon_urls: HashMap::new(),
             manifest_urls: HashMap::new(),
+            manifest: None,
         })
     }
 
-    pub async fn get_online_icons(&mut self) -> Result<Vec<(String, Rc<Icon>)>> {
-        debug!("Fetching online icons");
+    /// Finds the icon urls in the pages and manifests, fetch them with [`Self::fetch_icons`]
+    pub async fn fetch_icon_urls(&mut self) -> Result<()> {
+        debug!("Fetching online icon urls");
 
         let urls = [Some(self.url.clone()), self.base_url.clone()];
 
@@ -61,27 +154,182 @@ impl IconFetcher {
             let Some(url) = url else {
                 continue;
             };
-            let html_text = self.app.fetch.get_as_string(url.as_str()).await?;
-            let fragment = Html::parse_document(&html_text);
+            let (mut fragment, mut document_url) = self.get_document(&url).await?;
+
+            // Only one hop, to not end up in a refresh loop
+            if let Some(refresh_url) = Self::get_meta_refresh_url(&fragment, &document_url) {
+                info!(url = refresh_url.to_string(), "Following meta refresh");
+                match self.get_document(&refresh_url).await {
+                    Ok(document) => (fragment, document_url) = document,
+                    Err(error) => error!("{error:?}"),
+                }
+            }
+            if url == self.url {
+                self.document_url = Some(document_url.clone());
+            }
+
+            let base_href_url = Self::get_base_href_url(&fragment, &document_url);
+            let href_base_url = base_href_url.as_ref().unwrap_or(&document_url);
 
-            self.set_default_icon_urls(&url);
-            self.set_manifest_urls_from_html(&fragment, &url);
-            self.set_icon_urls_from_html(&fragment, &url);
+            self.set_default_icon_urls(&document_url);
+            self.set_manifest_urls_from_html(&fragment, href_base_url, &document_url);
+            self.set_icon_urls_from_html(&fragment, href_base_url);
         }
         self.set_icon_urls_from_manifests().await;
 
-        let icons = self.fetch_icons_from_urls().await;
+        if self.cancellable.is_cancelled() {
+            bail!("Fetching icon urls cancelled")
+        }
+
+        Ok(())
+    }
+
+    /// Calls `on_progress` for every icon as soon as its request is done
+    pub async fn fetch_icons<OnProgress>(&self, on_progress: OnProgress)
+    where
+        OnProgress: Fn(IconProgress) + 'static,
+    {
+        let icon_urls = self.icon_urls.clone();
+        let urls = icon_urls.keys().cloned().collect();
+        let total = icon_urls.len();
+        let mut done = 0;
+        let mut skipped = 0;
+
+        self.fetch_all(urls, move |url, result| {
+            done += 1;
+
+            let icon = if let Ok(image_bytes) = result {
+                match Icon::from_bytes(&image_bytes, icon_urls[&url].clone()) {
+                    Ok(icon) => Some((url, Rc::new(icon))),
+                    Err(error) => {
+                        info!(url, "Skipped icon: {error:?}");
+                        skipped += 1;
+                        None
+                    }
+                }
+            } else {
+                error!(url, "Failed to fetch image");
+                None
+            };
+
+            on_progress(IconProgress {
+                icon,
+                done,
+                total,
+                skipped,
+            });
+        })
+        .await;
+    }
+
+    /// Manifest of the page, or of the base url when the page has none
+    pub fn get_manifest(&self) -> Option<&WebAppManifest> {
+        self.manifest.as_ref()
+    }
+
+    /// Parsed page and the url it was served from
+    async fn get_document(&self, url: &Url) -> Result<(Html, Url)> {
+        let (html_text, document_url) = CancellableFuture::new(
+            self.app
+                .fetch
+                .get_page(url.as_str(), self.force, &self.cancellable),
+            self.cancellable.clone(),
+        )
+        .await??;
+
+        Ok((Html::parse_document(&html_text), document_url))
+    }
+
+    /// `<meta http-equiv="refresh" content="0; url=...">`
+    fn get_meta_refresh_url(html_fragment: &Html, document_url: &Url) -> Option<Url> {
+        let meta_selector = Selector::parse("meta[http-equiv][content]").ok()?;
+
+        let content = html_fragment
+            .select(&meta_selector)
+            .find(|element| {
+                element
+                    .value()
+                    .attr("http-equiv")
+                    .is_some_and(|http_equiv| http_equiv.trim().eq_ignore_ascii_case("refresh"))
+            })?
+            .value()
+            .attr("content")?;
+        debug!(content, "Meta refresh found");
+
+        let (_, target) = content.split_once([';', ','])?;
+        let target = target.trim_start();
+        let (key, target) = target.split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case("url") {
+            return None;
+        }
+        let target = target.trim().trim_matches(['\'', '"']);
+
+        document_url
+            .join(target)
+            .ok()
+            .filter(UrlExt::is_web_url)
+            .filter(|refresh_url| refresh_url != document_url)
+    }
+
+    /// `<base href>` changes what relative hrefs are resolved against
+    fn get_base_href_url(html_fragment: &Html, document_url: &Url) -> Option<Url> {
+        let base_selector = Selector::parse("base[href]").ok()?;
+        let href = html_fragment
+            .select(&base_selector)
+            .next()?
+            .value()
+            .attr("href")?;
+        debug!(href, "Base href found");
 
-        Ok(icons)
+        document_url.join(href.trim()).ok()
     }
 
     #[allow(clippy::unused_self)]
     fn get_href_as_absolute_url(&self, href: &str, url: &Url) -> Result<Url> {
-        let sanitized_url = url.sanitize();
-        let new_url = Url::parse(href).or(sanitized_url.join(href))?;
+        let new_url = url.join(href.trim())?;
         Ok(new_url)
     }
 
+    /// Keeps the most specific source when an url is found more than once.
+    /// A manifest can list the same icon for several purposes, those are merged.
+    fn add_icon_url(&mut self, icon_url: &Url, source: IconSource) {
+        let source = match (self.icon_urls.remove(icon_url.as_str()), source) {
+            (
+                Some(IconSource::Manifest {
+                    sizes,
+                    purpose,
+                    mime_type,
+                }),
+                IconSource::Manifest {
+                    sizes: new_sizes,
+                    purpose: new_purpose,
+                    mime_type: new_mime_type,
+                },
+            ) => IconSource::Manifest {
+                sizes: sizes.or(new_sizes),
+                purpose: Self::merge_purposes(purpose, new_purpose),
+                mime_type: mime_type.or(new_mime_type),
+            },
+            (Some(existing), source) if existing.get_priority() >= source.get_priority() => {
+                existing
+            }
+            (_, source) => source,
+        };
+        self.icon_urls.insert(icon_url.to_string(), source);
+    }
+
+    /// No purpose means `any`, so that wins over every other purpose
+    fn merge_purposes(purpose: Option<String>, new_purpose: Option<String>) -> Option<String> {
+        let (purpose, new_purpose) = (purpose?, new_purpose?);
+        let mut purposes: Vec<&str> = purpose.split_whitespace().collect();
+        for new in new_purpose.split_whitespace() {
+            if !purposes.contains(&new) {
+                purposes.push(new);
+            }
+        }
+        Some(purposes.join(" "))
+    }
+
     fn set_default_icon_urls(&mut self, url: &Url) {
         let sanitized_url = url.sanitize();
         let default_urls = [sanitized_url.join("favicon.ico").ok()];
@@ -90,11 +338,11 @@ impl IconFetcher {
             let Some(default) = default_url else {
                 continue;
             };
-            self.icon_urls.insert(default.to_string());
+            self.add_icon_url(&default, IconSource::Favicon);
         }
     }
 
-    fn set_manifest_urls_from_html(&mut self, html_fragment: &Html, url: &Url) {
+    fn set_manifest_urls_from_html(&mut self, html_fragment: &Html, url: &Url, document_url: &Url) {
         let Ok(manifest_selector) = Selector::parse("link[rel~=\"manifest\"]") else {
             return;
         };
@@ -106,100 +354,137 @@ impl IconFetcher {
                     continue;
                 };
                 info!(url = manifest_url.to_string(), "Manifest url found");
-                self.manifest_urls
-                    .insert(manifest_url.to_string(), (manifest_url, url.clone()));
+                self.manifest_urls.insert(
+                    manifest_url.to_string(),
+                    (manifest_url, document_url.clone()),
+                );
             }
         }
     }
 
     fn set_icon_urls_from_html(&mut self, html_fragment: &Html, url: &Url) {
-        let Ok(icon_selector) =
-            Selector::parse("link[rel~=\"icon\"], link[rel~=\"shortcut\"][rel~=\"icon\"]")
-        else {
-            return;
-        };
+        let selectors = [
+            "link[rel~=\"icon\"]",
+            "link[rel~=\"apple-touch-icon\"], link[rel~=\"apple-touch-icon-precomposed\"]",
+            "link[rel~=\"mask-icon\"]",
+        ];
 
-        for element in html_fragment.select(&icon_selector) {
-            if let Some(href) = element.value().attr("href") {
+        for (index, selector) in selectors.into_iter().enumerate() {
+            let Ok(icon_selector) = Selector::parse(selector) else {
+                continue;
+            };
+
+            for element in html_fragment.select(&icon_selector) {
+                let Some(href) = element.value().attr("href") else {
+                    continue;
+                };
                 debug!(href, "Favicon href found");
                 let Ok(icon_url) = self.get_href_as_absolute_url(href, url) else {
                     continue;
                 };
-                info!(url = icon_url.to_string(), "Favicon icon url found");
-                self.icon_urls.insert(icon_url.to_string());
+                let source = match index {
+                    0 => IconSource::Link {
+                        sizes: element.value().attr("sizes").map(str::to_string),
+                    },
+                    1 => IconSource::AppleTouchIcon,
+                    _ => IconSource::MaskIcon,
+                };
+                info!(
+                    url = icon_url.to_string(),
+                    ?source,
+                    "Favicon icon url found"
+                );
+                self.add_icon_url(&icon_url, source);
             }
         }
     }
 
     async fn set_icon_urls_from_manifests(&mut self) {
-        let mut manifest_handles = HashMap::new();
-
-        for (manifest_url, base_path_url) in self.manifest_urls.values() {
-            let app_clone = self.app.clone();
-            let url_clone = manifest_url.clone();
-            // Spawn in parallel on main thread
-            let handle = glib::spawn_future_local(async move {
-                app_clone.fetch.get_as_string(url_clone.as_str()).await
-            });
-            manifest_handles.insert((base_path_url, manifest_url), handle);
-        }
+        let manifest_urls = self.manifest_urls.keys().cloned().collect();
+        let manifest_jsons = Rc::new(RefCell::new(Vec::new()));
 
-        for ((base_path_url, manifest_url), handle) in manifest_handles {
-            let Ok(Ok(manifest_json)) = handle.await else {
+        let manifest_jsons_clone = manifest_jsons.clone();
+        self.fetch_all(manifest_urls, move |manifest_url, result| {
+            let Ok(bytes) = result else {
                 error!("Failed to fetch manifest: '{manifest_url}'");
-                continue;
+                return;
             };
-            let Ok(manifest) = serde_json::from_str::<ManifestJson>(&manifest_json) else {
-                continue;
-            };
-            let Some(icons) = manifest.icons else {
+            let manifest_json = String::from_utf8_lossy(&bytes).to_string();
+            manifest_jsons_clone
+                .borrow_mut()
+                .push((manifest_url, manifest_json));
+        })
+        .await;
+
+        for (manifest_url, manifest_json) in manifest_jsons.take() {
+            let Some((manifest_url, document_url)) = self.manifest_urls.get(&manifest_url).cloned()
+            else {
                 continue;
             };
-            for icon in icons {
-                let Some(icon_href) = icon.src else {
-                    continue;
-                };
-                debug!(href = icon_href, "Manifest href found");
-                let Ok(icon_url) = self.get_href_as_absolute_url(&icon_href, base_path_url) else {
+            let manifest = match WebAppManifest::parse(&manifest_json, &manifest_url, &document_url)
+            {
+                Ok(manifest) => manifest,
+                Err(error) => {
+                    error!("{error:?}");
                     continue;
+                }
+            };
+            for icon in &manifest.icons {
+                info!(icon_url = icon.url.to_string(), "Manifest icon url found");
+                let source = IconSource::Manifest {
+                    sizes: icon.sizes.clone(),
+                    purpose: icon.purpose.clone(),
+                    mime_type: icon.mime_type.clone(),
                 };
-                info!(icon_url = icon_url.to_string(), "Manifest icon url found");
-                self.icon_urls.insert(icon_url.to_string());
+                self.add_icon_url(&icon.url, source);
+            }
+            if self.manifest.is_none() || Some(&document_url) == self.document_url.as_ref() {
+                self.manifest = Some(manifest);
             }
         }
     }
 
-    async fn fetch_icons_from_urls(&mut self) -> Vec<(String, Rc<Icon>)> {
-        let mut icon_handles = HashMap::new();
-        let mut icons = Vec::new();
+    /// At most [`Self::MAX_CONCURRENT_FETCHES`] requests at a time, `on_done` is called
+    /// as they finish and not anymore after cancelling.
+    async fn fetch_all<OnDone>(&self, urls: Vec<String>, on_done: OnDone)
+    where
+        OnDone: FnMut(String, Result<Vec<u8>>) + 'static,
+    {
+        let queue = Rc::new(RefCell::new(VecDeque::from(urls)));
+        let on_done = Rc::new(RefCell::new(on_done));
 
-        for icon_url in &self.icon_urls {
-            let app_clone = self.app.clone();
-            let url_clone = icon_url.clone();
-            // Spawn in parallel on main thread
-            let handle =
-                glib::spawn_future_local(
-                    async move { app_clone.fetch.get_as_bytes(&url_clone).await },
-                );
+        let workers = (0..Self::MAX_CONCURRENT_FETCHES)
+            .map(|_| {
+                let app_clone = self.app.clone();
+                let cancellable_clone = self.cancellable.clone();
+                let force = self.force;
+                let queue_clone = queue.clone();
+                let on_done_clone = on_done.clone();
 
-            icon_handles.insert(icon_url, handle);
-        }
+                glib::spawn_future_local(async move {
+                    loop {
+                        let Some(url) = queue_clone.borrow_mut().pop_front() else {
+                            break;
+                        };
+                        let Ok(result) = CancellableFuture::new(
+                            app_clone
+                                .fetch
+                                .get_as_bytes(&url, force, &cancellable_clone),
+                            cancellable_clone.clone(),
+                        )
+                        .await
+                        else {
+                            debug!(url, "Fetch cancelled");
+                            break;
+                        };
+                        (on_done_clone.borrow_mut())(url, result);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
 
-        for (url, handle) in icon_handles {
-            let Ok(Ok(image_bytes)) = handle.await else {
-                error!(url, "Failed to fetch image");
-                continue;
-            };
-            let g_bytes = glib::Bytes::from(&image_bytes);
-            let stream = MemoryInputStream::from_bytes(&g_bytes);
-            let Ok(pixbuf) = Pixbuf::from_stream(&stream, Cancellable::NONE) else {
-                error!(url, "Failed to convert image");
-                continue;
-            };
-            let icon = Icon { pixbuf };
-            icons.push((url.clone(), Rc::new(icon)));
+        for worker in workers {
+            let _ = worker.await;
         }
-
-        icons
     }
 }
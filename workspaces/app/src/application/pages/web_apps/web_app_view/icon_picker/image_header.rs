@@ -0,0 +1,337 @@
+use anyhow::{Context, Result, bail};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Webp,
+    Ico,
+    Svg,
+}
+
+/// Format and declared size, read without decoding the image
+#[derive(Debug)]
+pub struct ImageHeader {
+    pub format: ImageFormat,
+    /// `None` for svg images, they are scalable
+    pub size: Option<(u32, u32)>,
+}
+/// 4096 x 4096, larger is not an icon
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+impl ImageHeader {
+    pub fn peek(bytes: &[u8]) -> Result<Self> {
+        let format = get_format(bytes).context("Unrecognized image format")?;
+        let size = match format {
+            ImageFormat::Png => Some(get_png_size(bytes)?),
+            ImageFormat::Jpeg => Some(get_jpeg_size(bytes)?),
+            ImageFormat::Gif => Some(get_gif_size(bytes)?),
+            ImageFormat::Bmp => Some(get_bmp_size(bytes)?),
+            ImageFormat::Webp => Some(get_webp_size(bytes)?),
+            ImageFormat::Ico => Some(
+                get_ico_frames(bytes)?
+                    .iter()
+                    .map(|frame| (frame.width, frame.height))
+                    .max_by_key(|(width, height)| u64::from(*width) * u64::from(*height))
+                    .context("Icon has no valid frames")?,
+            ),
+            ImageFormat::Svg => None,
+        };
+
+        Ok(Self { format, size })
+    }
+
+    /// Refuses images that would take too much memory to decode
+    pub fn check_pixel_budget(&self) -> Result<()> {
+        let Some((width, height)) = self.size else {
+            return Ok(());
+        };
+        check_pixel_budget(width, height)
+    }
+}
+
+/// A single image in an `.ico` file
+pub struct IcoFrame<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// Png data, or a bmp without file header
+    pub data: &'a [u8],
+    entry: &'a [u8],
+}
+impl IcoFrame<'_> {
+    pub fn is_png(&self) -> bool {
+        self.data.starts_with(PNG_SIGNATURE)
+    }
+
+    /// Every frame is decoded on its own, so each is checked
+    pub fn check_pixel_budget(&self) -> Result<()> {
+        check_pixel_budget(self.width, self.height)
+    }
+
+    /// An `.ico` file with only this frame, so the ico decoder can't pick another one
+    pub fn to_ico(&self) -> Vec<u8> {
+        let offset = u32::try_from(ICO_HEADER_SIZE + ICO_ENTRY_SIZE).unwrap_or_default();
+
+        let mut ico = Vec::with_capacity(ICO_HEADER_SIZE + ICO_ENTRY_SIZE + self.data.len());
+        ico.extend_from_slice(&[0, 0, 1, 0, 1, 0]);
+        ico.extend_from_slice(&self.entry[..12]);
+        ico.extend_from_slice(&offset.to_le_bytes());
+        ico.extend_from_slice(self.data);
+
+        ico
+    }
+}
+
+/// All frames of an `.ico` file, frames that point outside of the file or have a broken
+/// header are left out
+pub fn get_ico_frames(bytes: &[u8]) -> Result<Vec<IcoFrame<'_>>> {
+    let count = usize::from(read_u16_le(bytes, 4)?);
+    if count == 0 {
+        bail!("Icon has no frames")
+    }
+
+    let mut frames = Vec::new();
+    for index in 0..count {
+        let entry_start = ICO_HEADER_SIZE + index * ICO_ENTRY_SIZE;
+        let entry = bytes
+            .get(entry_start..entry_start + ICO_ENTRY_SIZE)
+            .context("Icon directory is truncated")?;
+        let size = usize::try_from(read_u32_le(entry, 8)?)?;
+        let offset = usize::try_from(read_u32_le(entry, 12)?)?;
+        let Some(data) = offset
+            .checked_add(size)
+            .and_then(|end| bytes.get(offset..end))
+        else {
+            continue;
+        };
+
+        // The directory size is a byte and not what the decoder uses, the image header is
+        let size = if data.starts_with(PNG_SIGNATURE) {
+            get_png_size(data)
+        } else {
+            get_ico_bmp_size(data)
+        };
+        let Ok((width, height)) = size else {
+            continue;
+        };
+
+        frames.push(IcoFrame {
+            width,
+            height,
+            data,
+            entry,
+        });
+    }
+
+    Ok(frames)
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const ICO_HEADER_SIZE: usize = 6;
+const ICO_ENTRY_SIZE: usize = 16;
+
+fn get_format(bytes: &[u8]) -> Option<ImageFormat> {
+    if bytes.starts_with(PNG_SIGNATURE) {
+        return Some(ImageFormat::Png);
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some(ImageFormat::Jpeg);
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some(ImageFormat::Gif);
+    }
+    if bytes.starts_with(b"BM") {
+        return Some(ImageFormat::Bmp);
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return Some(ImageFormat::Webp);
+    }
+    // Icons and cursors
+    if bytes.starts_with(&[0, 0, 1, 0]) || bytes.starts_with(&[0, 0, 2, 0]) {
+        return Some(ImageFormat::Ico);
+    }
+
+    let start = skip_xml_prolog(bytes);
+    if start.len() >= 4 && start[..4].eq_ignore_ascii_case(b"<svg") {
+        return Some(ImageFormat::Svg);
+    }
+
+    None
+}
+
+/// Skips the xml declaration, comments and doctype that can come before the root element,
+/// these have no size limit so a fixed prefix is not enough.
+fn skip_xml_prolog(bytes: &[u8]) -> &[u8] {
+    let mut rest = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+
+    loop {
+        rest = rest.trim_ascii_start();
+        let end = if rest.starts_with(b"<?") {
+            find_bytes(rest, b"?>").map(|position| position + 2)
+        } else if rest.starts_with(b"<!--") {
+            find_bytes(rest, b"-->").map(|position| position + 3)
+        } else if rest.len() >= 9 && rest[..9].eq_ignore_ascii_case(b"<!doctype") {
+            // The internal subset can contain `>` itself
+            let close = rest.iter().position(|&byte| byte == b'>');
+            match rest.iter().position(|&byte| byte == b'[') {
+                Some(open) if close.is_none_or(|close| open < close) => find_bytes(rest, b"]")
+                    .and_then(|subset_end| {
+                        rest[subset_end..]
+                            .iter()
+                            .position(|&byte| byte == b'>')
+                            .map(|position| subset_end + position + 1)
+                    }),
+                _ => close.map(|position| position + 1),
+            }
+        } else {
+            return rest;
+        };
+
+        let Some(end) = end else {
+            return &[];
+        };
+        rest = &rest[end..];
+    }
+}
+
+fn find_bytes(bytes: &[u8], needle: &[u8]) -> Option<usize> {
+    bytes
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn get_png_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    if bytes.get(12..16) != Some(b"IHDR") {
+        bail!("Png has no header")
+    }
+
+    Ok((read_u32_be(bytes, 16)?, read_u32_be(bytes, 20)?))
+}
+
+/// Bmp frames have no file header, the height includes the transparency mask
+fn get_ico_bmp_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    if read_u32_le(bytes, 0)? == 12 {
+        return Ok((
+            u32::from(read_u16_le(bytes, 4)?),
+            u32::from(read_u16_le(bytes, 6)?) / 2,
+        ));
+    }
+
+    let width = read_u32_le(bytes, 4)?.cast_signed().unsigned_abs();
+    let height = read_u32_le(bytes, 8)?.cast_signed().unsigned_abs() / 2;
+
+    Ok((width, height))
+}
+
+fn get_gif_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    Ok((
+        u32::from(read_u16_le(bytes, 6)?),
+        u32::from(read_u16_le(bytes, 8)?),
+    ))
+}
+
+fn get_bmp_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    // Old OS/2 headers have 16 bit sizes
+    if read_u32_le(bytes, 14)? == 12 {
+        return Ok((
+            u32::from(read_u16_le(bytes, 18)?),
+            u32::from(read_u16_le(bytes, 20)?),
+        ));
+    }
+
+    // A negative height is a top-down image
+    let width = read_u32_le(bytes, 18)?.cast_signed().unsigned_abs();
+    let height = read_u32_le(bytes, 22)?.cast_signed().unsigned_abs();
+
+    Ok((width, height))
+}
+
+fn get_webp_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    match bytes.get(12..16) {
+        Some(b"VP8 ") => Ok((
+            u32::from(read_u16_le(bytes, 26)? & 0x3FFF),
+            u32::from(read_u16_le(bytes, 28)? & 0x3FFF),
+        )),
+        Some(b"VP8L") => {
+            let bits = read_u32_le(bytes, 21)?;
+            Ok(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+        }
+        Some(b"VP8X") => Ok((read_u24_le(bytes, 24)? + 1, read_u24_le(bytes, 27)? + 1)),
+        _ => bail!("Unknown webp chunk"),
+    }
+}
+
+fn get_jpeg_size(bytes: &[u8]) -> Result<(u32, u32)> {
+    let mut position = 2;
+
+    loop {
+        if *bytes.get(position).context("Jpeg has no frame header")? != 0xFF {
+            bail!("Invalid jpeg marker")
+        }
+        let marker = *bytes
+            .get(position + 1)
+            .context("Jpeg has no frame header")?;
+        match marker {
+            // Fill byte
+            0xFF => {
+                position += 1;
+            }
+            // Markers without a length
+            0x01 | 0xD0..=0xD7 => {
+                position += 2;
+            }
+            0xD9 | 0xDA => bail!("Jpeg has no frame header"),
+            // Start of frame, except the huffman, arithmetic and extension markers
+            0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                let height = read_u16_be(bytes, position + 5)?;
+                let width = read_u16_be(bytes, position + 7)?;
+                return Ok((u32::from(width), u32::from(height)));
+            }
+            _ => {
+                let length = read_u16_be(bytes, position + 2)?;
+                position += 2 + usize::from(length);
+            }
+        }
+    }
+}
+
+fn check_pixel_budget(width: u32, height: u32) -> Result<()> {
+    if width == 0 || height == 0 {
+        bail!("Image has no size: {width} x {height}")
+    }
+    if u64::from(width) * u64::from(height) > MAX_PIXELS {
+        bail!("Image is too large: {width} x {height}")
+    }
+
+    Ok(())
+}
+
+fn read_bytes<const N: usize>(bytes: &[u8], position: usize) -> Result<[u8; N]> {
+    bytes
+        .get(position..position + N)
+        .and_then(|slice| slice.try_into().ok())
+        .context("Image header is truncated")
+}
+
+fn read_u16_le(bytes: &[u8], position: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(read_bytes(bytes, position)?))
+}
+
+fn read_u16_be(bytes: &[u8], position: usize) -> Result<u16> {
+    Ok(u16::from_be_bytes(read_bytes(bytes, position)?))
+}
+
+fn read_u24_le(bytes: &[u8], position: usize) -> Result<u32> {
+    let [low, middle, high] = read_bytes(bytes, position)?;
+    Ok(u32::from_le_bytes([low, middle, high, 0]))
+}
+
+fn read_u32_le(bytes: &[u8], position: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(bytes, position)?))
+}
+
+fn read_u32_be(bytes: &[u8], position: usize) -> Result<u32> {
+    Ok(u32::from_be_bytes(read_bytes(bytes, position)?))
+}
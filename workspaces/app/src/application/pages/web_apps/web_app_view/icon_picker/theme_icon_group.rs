@@ -0,0 +1,254 @@
+use crate::application::App;
+use gtk::{
+    FlowBox, Image, Label, Orientation, SearchEntry, SelectionMode,
+    prelude::{BoxExt, EditableExt, FlowBoxChildExt, WidgetExt},
+};
+use libadwaita::{PreferencesGroup, PreferencesRow, prelude::PreferencesGroupExt};
+use std::{cell::RefCell, rc::Rc};
+use tracing::debug;
+use url::Url;
+
+/// Searches the icons of the user's icon theme, `Icon=` then gets the name
+pub struct ThemeIconGroup {
+    app: Rc<App>,
+    pref_group: PreferencesGroup,
+    search_entry: SearchEntry,
+    flow_box: FlowBox,
+    empty_label: Label,
+    /// Search terms from the app name and domain
+    suggestions: RefCell<Vec<String>>,
+    selected: RefCell<Option<String>>,
+    on_select: RefCell<Option<Box<dyn Fn()>>>,
+}
+impl ThemeIconGroup {
+    const MAX_RESULTS: usize = 16;
+    /// Parts of a domain that say nothing about the app
+    const IGNORED_TERMS: [&str; 8] = ["www", "app", "apps", "web", "open", "com", "org", "net"];
+
+    pub fn new(app: &Rc<App>) -> Rc<Self> {
+        let search_entry = SearchEntry::builder()
+            .placeholder_text("Search icons")
+            .margin_top(6)
+            .margin_bottom(6)
+            .margin_start(6)
+            .margin_end(6)
+            .build();
+        let flow_box = FlowBox::builder()
+            .column_spacing(10)
+            .row_spacing(10)
+            .homogeneous(true)
+            .max_children_per_line(4)
+            .min_children_per_line(4)
+            .selection_mode(SelectionMode::Single)
+            .margin_bottom(6)
+            .build();
+        let empty_label = Label::builder()
+            .label("No matching icons")
+            .css_classes(["dimmed"])
+            .margin_top(12)
+            .margin_bottom(12)
+            .build();
+
+        let content_box = gtk::Box::new(Orientation::Vertical, 0);
+        content_box.append(&search_entry);
+        content_box.append(&flow_box);
+        content_box.append(&empty_label);
+
+        let pref_row = PreferencesRow::builder().child(&content_box).build();
+        let pref_group = PreferencesGroup::builder()
+            .title("Icon theme")
+            .description("Follows the icon theme of your desktop")
+            .build();
+        pref_group.add(&pref_row);
+
+        Rc::new(Self {
+            app: app.clone(),
+            pref_group,
+            search_entry,
+            flow_box,
+            empty_label,
+            suggestions: RefCell::new(Vec::new()),
+            selected: RefCell::new(None),
+            on_select: RefCell::new(None),
+        })
+    }
+
+    pub fn init<OnSelect>(self: &Rc<Self>, on_select: OnSelect)
+    where
+        OnSelect: Fn() + 'static,
+    {
+        *self.on_select.borrow_mut() = Some(Box::new(on_select));
+
+        let self_clone = self.clone();
+        self.search_entry.connect_search_changed(move |_| {
+            self_clone.reload_results();
+        });
+
+        let self_clone = self.clone();
+        self.flow_box
+            .connect_child_activated(move |_, flow_box_child| {
+                let icon_name = flow_box_child
+                    .child()
+                    .map(|widget| widget.widget_name().to_string());
+                debug!(icon_name, "Selected theme icon");
+                self_clone.selected.borrow_mut().clone_from(&icon_name);
+
+                if let Some(on_select) = self_clone.on_select.borrow().as_ref() {
+                    on_select();
+                }
+            });
+    }
+
+    pub fn get_pref_group(&self) -> &PreferencesGroup {
+        &self.pref_group
+    }
+
+    pub fn get_selected(&self) -> Option<String> {
+        self.selected.borrow().clone()
+    }
+
+    /// When the user picks another icon
+    pub fn unselect(&self) {
+        *self.selected.borrow_mut() = None;
+        self.flow_box.unselect_all();
+    }
+
+    /// Suggests icons for the app, the search is kept
+    pub fn reload(&self, name: Option<&str>, url: Option<&str>) {
+        let domain_terms = url
+            .and_then(|url| Url::parse(url).ok())
+            .and_then(|url| url.host_str().map(str::to_string))
+            .map(|host| {
+                let mut labels: Vec<_> = host.split('.').map(str::to_string).collect();
+                // Top level domain
+                if labels.len() > 1 {
+                    labels.pop();
+                }
+                labels
+            })
+            .unwrap_or_default();
+        let name_terms: Vec<_> = name
+            .map(|name| name.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default();
+
+        *self.suggestions.borrow_mut() =
+            Self::get_terms(name_terms.into_iter().chain(domain_terms));
+        self.unselect();
+        self.reload_results();
+    }
+
+    fn reload_results(&self) {
+        let search = self.search_entry.text();
+        let terms = if search.trim().is_empty() {
+            self.suggestions.borrow().clone()
+        } else {
+            Self::get_terms(search.split_whitespace().map(str::to_string))
+        };
+
+        while let Some(child) = self.flow_box.first_child() {
+            self.flow_box.remove(&child);
+        }
+
+        let icon_names = self.find_icons(&terms);
+        for icon_name in &icon_names {
+            let frame = gtk::Box::new(Orientation::Vertical, 6);
+            frame.set_widget_name(icon_name);
+            frame.set_tooltip_text(Some(icon_name));
+            let image = Image::builder().icon_name(icon_name).pixel_size(48).build();
+            frame.append(&image);
+            let label = Label::builder()
+                .label(icon_name)
+                .css_classes(["caption", "dimmed"])
+                .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                .max_width_chars(12)
+                .build();
+            frame.append(&label);
+
+            self.flow_box.insert(&frame, -1);
+        }
+
+        // Also stays picked when a search leaves it out
+        if let Some(selected) = self.selected.borrow().as_deref()
+            && let Some(index) = icon_names
+                .iter()
+                .position(|icon_name| icon_name == selected)
+            && let Some(flow_box_child) = self
+                .flow_box
+                .child_at_index(i32::try_from(index).unwrap_or_default())
+        {
+            self.flow_box.select_child(&flow_box_child);
+        }
+        self.flow_box.set_visible(!icon_names.is_empty());
+        self.empty_label.set_visible(icon_names.is_empty());
+    }
+
+    /// Best matches first: the whole name, then the last part of a reverse DNS name or
+    /// the start, then anywhere in the name
+    fn find_icons(&self, terms: &[String]) -> Vec<String> {
+        if terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<_> = self
+            .app
+            .icon_theme
+            .icon_names()
+            .iter()
+            .map(ToString::to_string)
+            .filter(|icon_name| !icon_name.ends_with("-symbolic"))
+            .filter_map(|icon_name| {
+                let lowercase = icon_name.to_lowercase();
+                let last_part = lowercase.rsplit('.').next().unwrap_or_default();
+                let score = terms
+                    .iter()
+                    .map(|term| {
+                        if lowercase == *term {
+                            3
+                        } else if last_part == term || lowercase.starts_with(term.as_str()) {
+                            2
+                        } else {
+                            u8::from(lowercase.contains(term.as_str()))
+                        }
+                    })
+                    .max()
+                    .unwrap_or_default();
+
+                (score > 0).then_some((score, icon_name))
+            })
+            .collect();
+
+        matches.sort_by(|(score_a, name_a), (score_b, name_b)| {
+            score_b
+                .cmp(score_a)
+                .then_with(|| name_a.len().cmp(&name_b.len()))
+                .then_with(|| name_a.cmp(name_b))
+        });
+
+        matches
+            .into_iter()
+            .take(Self::MAX_RESULTS)
+            .map(|(_, icon_name)| icon_name)
+            .collect()
+    }
+
+    /// Lowercase, without short and meaningless parts or duplicates
+    fn get_terms(words: impl Iterator<Item = String>) -> Vec<String> {
+        let mut terms = Vec::new();
+        for word in words {
+            let term: String = word
+                .to_lowercase()
+                .chars()
+                .filter(|char| char.is_alphanumeric() || *char == '-')
+                .collect();
+            if term.chars().count() < 3
+                || Self::IGNORED_TERMS.contains(&term.as_str())
+                || terms.contains(&term)
+            {
+                continue;
+            }
+            terms.push(term);
+        }
+
+        terms
+    }
+}
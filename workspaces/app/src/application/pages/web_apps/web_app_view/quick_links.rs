@@ -0,0 +1,249 @@
+use crate::application::App;
+use common::{
+    desktop_file::{DesktopAction, DesktopFile},
+    url::UrlExt,
+};
+use gtk::{Align, InputPurpose, ListBox, SelectionMode};
+use libadwaita::{
+    ActionRow, AlertDialog, ButtonRow, EntryRow, PreferencesGroup, ResponseAppearance,
+    gtk::{
+        self, Button,
+        prelude::{ButtonExt, EditableExt, WidgetExt},
+    },
+    prelude::{ActionRowExt, AdwDialogExt, AlertDialogExt, PreferencesGroupExt},
+};
+use std::{cell::RefCell, rc::Rc};
+use tracing::debug;
+use url::Url;
+
+pub struct QuickLinks {
+    app: Rc<App>,
+    desktop_file: Rc<RefCell<DesktopFile>>,
+    pref_group: PreferencesGroup,
+    add_button_row: ButtonRow,
+    rows: RefCell<Vec<ActionRow>>,
+    on_change: RefCell<Option<Box<dyn Fn()>>>,
+}
+impl QuickLinks {
+    pub const DIALOG_ADD: &str = "add";
+    pub const DIALOG_SAVE: &str = "save";
+    pub const DIALOG_CANCEL: &str = "cancel";
+
+    pub fn new(app: &Rc<App>, desktop_file: &Rc<RefCell<DesktopFile>>) -> Rc<Self> {
+        let pref_group = PreferencesGroup::builder()
+            .title("Quick links")
+            .description("Shown when right-clicking the app")
+            .build();
+        let add_button_row = ButtonRow::builder()
+            .title("Add quick link")
+            .start_icon_name("list-add-symbolic")
+            .build();
+        pref_group.add(&add_button_row);
+
+        Rc::new(Self {
+            app: app.clone(),
+            desktop_file: desktop_file.clone(),
+            pref_group,
+            add_button_row,
+            rows: RefCell::new(Vec::new()),
+            on_change: RefCell::new(None),
+        })
+    }
+
+    pub fn init<OnChange>(self: &Rc<Self>, on_change: OnChange)
+    where
+        OnChange: Fn() + 'static,
+    {
+        *self.on_change.borrow_mut() = Some(Box::new(on_change));
+
+        let self_clone = self.clone();
+        self.add_button_row.connect_activated(move |_| {
+            self_clone.show_dialog(None);
+        });
+
+        self.reload();
+    }
+
+    pub fn get_pref_group(&self) -> &PreferencesGroup {
+        &self.pref_group
+    }
+
+    /// Replace the quick links without triggering the change callback
+    pub fn set_actions(self: &Rc<Self>, actions: &[DesktopAction]) {
+        self.desktop_file.borrow_mut().set_actions(actions);
+        self.reload();
+    }
+
+    pub fn reload(self: &Rc<Self>) {
+        for row in self.rows.borrow_mut().drain(..) {
+            self.pref_group.remove(&row);
+        }
+        self.pref_group.remove(&self.add_button_row);
+
+        let actions = self.desktop_file.borrow().get_actions();
+        let mut rows = self.rows.borrow_mut();
+
+        for (index, action) in actions.iter().enumerate() {
+            let row = self.build_row(&actions, index, action);
+            self.pref_group.add(&row);
+            rows.push(row);
+        }
+        self.pref_group.add(&self.add_button_row);
+    }
+
+    fn change_actions(self: &Rc<Self>, actions: &[DesktopAction]) {
+        self.set_actions(actions);
+
+        if let Some(on_change) = self.on_change.borrow().as_ref() {
+            on_change();
+        }
+    }
+
+    fn build_row(
+        self: &Rc<Self>,
+        actions: &[DesktopAction],
+        index: usize,
+        action: &DesktopAction,
+    ) -> ActionRow {
+        let row = ActionRow::builder()
+            .title(&action.name)
+            .subtitle(&action.url)
+            .title_lines(1)
+            .subtitle_lines(1)
+            .activatable(true)
+            .build();
+        let self_clone = self.clone();
+        row.connect_activated(move |_| {
+            self_clone.show_dialog(Some(index));
+        });
+
+        let edit_button = Self::build_row_button("document-edit-symbolic", "Edit");
+        let self_clone = self.clone();
+        edit_button.connect_clicked(move |_| {
+            self_clone.show_dialog(Some(index));
+        });
+
+        let up_button = Self::build_row_button("go-up-symbolic", "Move up");
+        up_button.set_sensitive(index > 0);
+        let self_clone = self.clone();
+        let actions_clone = actions.to_vec();
+        up_button.connect_clicked(move |_| {
+            let mut actions = actions_clone.clone();
+            actions.swap(index, index - 1);
+            self_clone.change_actions(&actions);
+        });
+
+        let down_button = Self::build_row_button("go-down-symbolic", "Move down");
+        down_button.set_sensitive(index + 1 < actions.len());
+        let self_clone = self.clone();
+        let actions_clone = actions.to_vec();
+        down_button.connect_clicked(move |_| {
+            let mut actions = actions_clone.clone();
+            actions.swap(index, index + 1);
+            self_clone.change_actions(&actions);
+        });
+
+        let remove_button = Self::build_row_button("user-trash-symbolic", "Remove");
+        let self_clone = self.clone();
+        let actions_clone = actions.to_vec();
+        remove_button.connect_clicked(move |_| {
+            let mut actions = actions_clone.clone();
+            let removed = actions.remove(index);
+            debug!(name = removed.name, "Removing quick link");
+            self_clone.change_actions(&actions);
+        });
+
+        row.add_suffix(&edit_button);
+        row.add_suffix(&up_button);
+        row.add_suffix(&down_button);
+        row.add_suffix(&remove_button);
+
+        row
+    }
+
+    fn build_row_button(icon_name: &str, tooltip: &str) -> Button {
+        Button::builder()
+            .icon_name(icon_name)
+            .tooltip_text(tooltip)
+            .valign(Align::Center)
+            .css_classes(["flat"])
+            .build()
+    }
+
+    /// Adds a quick link, or edits the one at `edit_index`
+    fn show_dialog(self: &Rc<Self>, edit_index: Option<usize>) {
+        let edited_action = edit_index
+            .and_then(|index| self.desktop_file.borrow().get_actions().get(index).cloned());
+        let (name, url) = match &edited_action {
+            Some(action) => (action.name.clone(), action.url.clone()),
+            None => (
+                String::new(),
+                self.desktop_file.borrow().get_url().unwrap_or_default(),
+            ),
+        };
+        let (heading, response, response_label) = if edited_action.is_some() {
+            ("Edit quick link", Self::DIALOG_SAVE, "_Save")
+        } else {
+            ("Add quick link", Self::DIALOG_ADD, "_Add")
+        };
+
+        let name_row = EntryRow::builder()
+            .title("Name")
+            .text(name)
+            .input_purpose(InputPurpose::Name)
+            .build();
+        let url_row = EntryRow::builder()
+            .title("URL")
+            .text(url)
+            .input_purpose(InputPurpose::Url)
+            .build();
+        let list_box = ListBox::builder()
+            .selection_mode(SelectionMode::None)
+            .css_classes(["boxed-list"])
+            .build();
+        list_box.append(&name_row);
+        list_box.append(&url_row);
+
+        let dialog = AlertDialog::builder()
+            .heading(heading)
+            .width_request(400)
+            .extra_child(&list_box)
+            .build();
+        dialog.add_response(Self::DIALOG_CANCEL, "_Cancel");
+        dialog.add_response(response, response_label);
+        dialog.set_response_appearance(response, ResponseAppearance::Suggested);
+        dialog.set_default_response(Some(Self::DIALOG_CANCEL));
+        dialog.set_close_response(Self::DIALOG_CANCEL);
+
+        let validate = {
+            let dialog = dialog.clone();
+            let name_row = name_row.clone();
+            let url_row = url_row.clone();
+            move || {
+                let is_valid = !name_row.text().trim().is_empty()
+                    && Url::parse(&url_row.text()).is_ok_and(|url| url.is_web_url());
+                dialog.set_response_enabled(response, is_valid);
+            }
+        };
+        validate();
+        let validate_clone = validate.clone();
+        name_row.connect_changed(move |_| validate_clone());
+        url_row.connect_changed(move |_| validate());
+
+        let self_clone = self.clone();
+        dialog.connect_response(Some(response), move |_, _| {
+            let mut actions = self_clone.desktop_file.borrow().get_actions();
+            let action = DesktopAction {
+                name: name_row.text().trim().to_string(),
+                url: url_row.text().to_string(),
+            };
+            match edit_index.and_then(|index| actions.get_mut(index)) {
+                Some(edited) => *edited = action,
+                None => actions.push(action),
+            }
+            self_clone.change_actions(&actions);
+        });
+
+        dialog.present(Some(&self.app.window.adw_window));
+    }
+}
@@ -79,7 +79,7 @@ impl AppMenu {
                 let dialog = AlertDialog::builder()
                     .heading(format!("Reset {}?", config::APP_NAME.get_value()))
                     .body(
-                        "This will reset the config files (e.g.: browser configs).\n\n\
+                        "This will remove your own config files (e.g.: browser configs).\n\n\
                         It will not remove your create web apps.",
                     )
                     .build();
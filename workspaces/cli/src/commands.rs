@@ -0,0 +1,532 @@
+use crate::{Command, CreateArgs, EditArgs};
+use anyhow::{Context, Result, bail};
+use common::{
+    app_dirs::AppDirs,
+    assets,
+    browsers::{Browser, BrowserConfigs, Installation},
+    desktop_file::{DesktopAction, DesktopFile},
+    monogram,
+    url::UrlExt,
+    utils::{self, command::HostCommand},
+};
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use tracing::{debug, error, info};
+use url::Url;
+
+#[derive(Serialize)]
+struct WebAppOutput {
+    id: String,
+    name: String,
+    url: String,
+    browser_id: Option<String>,
+    browser: Option<String>,
+    isolate: bool,
+    maximize: bool,
+    icon: Option<PathBuf>,
+    profile: Option<PathBuf>,
+    quick_links: Vec<QuickLinkOutput>,
+    desktop_file: PathBuf,
+}
+impl WebAppOutput {
+    fn from_desktop_file(desktop_file: &DesktopFile) -> Self {
+        let browser = desktop_file.get_browser();
+
+        Self {
+            id: desktop_file.get_id().unwrap_or_default(),
+            name: desktop_file.get_name().unwrap_or_default(),
+            url: desktop_file.get_url().unwrap_or_default(),
+            browser_id: browser.as_ref().map(|browser| browser.id.clone()),
+            browser: browser.map(|browser| browser.get_name_with_installation()),
+            isolate: desktop_file.get_isolated().unwrap_or(false),
+            maximize: desktop_file.get_maximized().unwrap_or(false),
+            icon: desktop_file.get_icon_path(),
+            profile: desktop_file.get_profile_path(),
+            quick_links: desktop_file
+                .get_actions()
+                .into_iter()
+                .map(|action| QuickLinkOutput {
+                    name: action.name,
+                    url: action.url,
+                })
+                .collect(),
+            desktop_file: desktop_file.get_path(),
+        }
+    }
+
+    fn print_text(&self) {
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+        println!("{}", self.name);
+        println!("  Id:           {}", self.id);
+        println!("  URL:          {}", self.url);
+        println!(
+            "  Browser:      {}",
+            self.browser.as_deref().unwrap_or("Not installed")
+        );
+        println!("  Isolate:      {}", yes_no(self.isolate));
+        println!("  Maximize:     {}", yes_no(self.maximize));
+        if let Some(icon) = &self.icon {
+            println!("  Icon:         {}", icon.display());
+        }
+        if let Some(profile) = &self.profile {
+            println!("  Profile:      {}", profile.display());
+        }
+        for quick_link in &self.quick_links {
+            println!("  Quick link:   {} ({})", quick_link.name, quick_link.url);
+        }
+        println!("  Desktop file: {}", self.desktop_file.display());
+    }
+}
+
+#[derive(Serialize)]
+struct QuickLinkOutput {
+    name: String,
+    url: String,
+}
+
+#[derive(Serialize)]
+struct BrowserOutput {
+    id: String,
+    name: String,
+    installation: String,
+    installed: bool,
+    can_isolate: bool,
+    can_start_maximized: bool,
+    issues: Vec<String>,
+}
+impl BrowserOutput {
+    fn from_browser(browser: &Browser) -> Self {
+        let installation = match browser.installation {
+            Installation::Flatpak => "flatpak",
+            Installation::Snap => "snap",
+            Installation::System => "system",
+            Installation::Path => "path",
+            Installation::None => "none",
+        };
+
+        Self {
+            id: browser.id.clone(),
+            name: browser.name.clone(),
+            installation: installation.to_string(),
+            installed: browser.is_installed(),
+            can_isolate: browser.can_isolate,
+            can_start_maximized: browser.can_start_maximized,
+            issues: browser.issues.clone(),
+        }
+    }
+}
+
+pub struct Cli {
+    json: bool,
+    app_dirs: Rc<AppDirs>,
+    browser_configs: Rc<BrowserConfigs>,
+}
+impl Cli {
+    pub fn new(json: bool) -> Self {
+        let app_dirs = AppDirs::new();
+        let browser_configs = BrowserConfigs::new(&app_dirs);
+
+        Self {
+            json,
+            app_dirs,
+            browser_configs,
+        }
+    }
+
+    pub fn init(&self) -> Result<()> {
+        // Order matters!
+        self.app_dirs.init()?;
+        assets::init(&self.app_dirs)?;
+        self.browser_configs.init();
+
+        Ok(())
+    }
+
+    pub fn run(&self, command: Command) -> Result<()> {
+        match command {
+            Command::List => self.list(),
+            Command::Show { id } => self.show(&id),
+            Command::Browsers => self.browsers(),
+            Command::Create(args) => self.create(&args),
+            Command::Edit(args) => self.edit(&args),
+            Command::Delete { id } => self.delete(&id),
+            Command::Run { id } => self.run_app(&id),
+            Command::Migrate => self.migrate(),
+        }
+    }
+
+    fn list(&self) -> Result<()> {
+        let web_apps = self
+            .get_owned_desktop_files()
+            .iter()
+            .map(WebAppOutput::from_desktop_file)
+            .collect::<Vec<_>>();
+
+        if self.json {
+            return Self::print_json(&web_apps);
+        }
+
+        if web_apps.is_empty() {
+            println!("No Web Apps found");
+            return Ok(());
+        }
+
+        for web_app in &web_apps {
+            println!(
+                "{:<10} {:<30} {:<30} {}",
+                web_app.id,
+                web_app.name,
+                web_app.browser.as_deref().unwrap_or("Not installed"),
+                web_app.url
+            );
+        }
+
+        Ok(())
+    }
+
+    fn show(&self, id: &str) -> Result<()> {
+        let desktop_file = self.get_desktop_file_by_id(id)?;
+        self.print_web_app(&desktop_file)
+    }
+
+    fn browsers(&self) -> Result<()> {
+        let browsers = self
+            .browser_configs
+            .get_all_browsers()
+            .iter()
+            .chain(self.browser_configs.get_uninstalled_browsers().iter())
+            .filter(|browser| !browser.id.is_empty())
+            .map(|browser| BrowserOutput::from_browser(browser))
+            .collect::<Vec<_>>();
+
+        if self.json {
+            return Self::print_json(&browsers);
+        }
+
+        for browser in &browsers {
+            println!(
+                "{:<50} {:<25} {}",
+                browser.id,
+                browser.name,
+                if browser.installed {
+                    &browser.installation
+                } else {
+                    "not installed"
+                }
+            );
+        }
+
+        Ok(())
+    }
+
+    fn create(&self, args: &CreateArgs) -> Result<()> {
+        let mut desktop_file = DesktopFile::new(&self.browser_configs, &self.app_dirs);
+
+        if let Err(error) = (|| -> Result<()> {
+            let browser = self.get_installed_browser(&args.browser)?;
+            let is_isolated = Self::validate_isolate(&browser, args.switches.get_isolate(), false)?;
+            let is_maximized =
+                Self::validate_maximize(&browser, args.switches.get_maximize(), false)?;
+
+            desktop_file.set_name(&Self::validate_name(&args.name)?);
+            desktop_file.set_url(&Self::validate_url(&args.url)?);
+            desktop_file.set_browser(&browser);
+            desktop_file.set_isolated(is_isolated);
+            desktop_file.set_maximized(is_maximized);
+
+            desktop_file.set_actions(&Self::validate_quick_links(&args.quick_links)?);
+
+            match &args.icon {
+                Some(icon) => Self::set_icon(&mut desktop_file, icon)?,
+                None => desktop_file.install_icon_from_svg(&monogram::build_monogram(
+                    desktop_file.get_name().as_deref(),
+                    desktop_file.get_url().as_deref(),
+                ))?,
+            }
+            Self::set_profile(&mut desktop_file)?;
+
+            desktop_file.save()?;
+            Ok(())
+        })() {
+            // Clean up the icon and profile that may have been created
+            let _ = desktop_file.delete();
+            return Err(error);
+        }
+
+        info!(
+            "Created web app: {}",
+            desktop_file.get_name().unwrap_or_default()
+        );
+        self.print_web_app(&desktop_file)
+    }
+
+    fn edit(&self, args: &EditArgs) -> Result<()> {
+        let mut desktop_file = self.get_desktop_file_by_id(&args.id)?;
+
+        if let Some(name) = &args.name {
+            desktop_file.set_name(&Self::validate_name(name)?);
+        }
+        if let Some(url) = &args.url {
+            desktop_file.set_url(&Self::validate_url(url)?);
+        }
+        if let Some(browser_id) = &args.browser {
+            let browser = self.get_installed_browser(browser_id)?;
+            desktop_file.set_browser(&browser);
+        }
+
+        let browser = desktop_file
+            .get_browser()
+            .filter(|browser| browser.is_installed())
+            .context("Browser of this web app is not installed, please pick one with --browser")?;
+
+        let is_isolated = Self::validate_isolate(
+            &browser,
+            args.switches.get_isolate(),
+            desktop_file.get_isolated().unwrap_or(false),
+        )?;
+        let is_maximized = Self::validate_maximize(
+            &browser,
+            args.switches.get_maximize(),
+            desktop_file.get_maximized().unwrap_or(false),
+        )?;
+        desktop_file.set_isolated(is_isolated);
+        desktop_file.set_maximized(is_maximized);
+
+        if args.no_quick_links {
+            desktop_file.set_actions(&[]);
+        } else if !args.quick_links.is_empty() {
+            desktop_file.set_actions(&Self::validate_quick_links(&args.quick_links)?);
+        }
+
+        if let Some(icon) = &args.icon {
+            Self::set_icon(&mut desktop_file, icon)?;
+        }
+        Self::set_profile(&mut desktop_file)?;
+
+        desktop_file.save()?;
+
+        info!(
+            "Saved web app: {}",
+            desktop_file.get_name().unwrap_or_default()
+        );
+        self.print_web_app(&desktop_file)
+    }
+
+    fn delete(&self, id: &str) -> Result<()> {
+        let desktop_file = self.get_desktop_file_by_id(id)?;
+        let name = desktop_file.get_name().unwrap_or_default();
+
+        desktop_file.delete()?;
+
+        if self.json {
+            return Self::print_json(&serde_json::json!({ "id": id, "deleted": true }));
+        }
+
+        println!("Deleted web app: {name}");
+        Ok(())
+    }
+
+    fn run_app(&self, id: &str) -> Result<()> {
+        let desktop_file = self.get_desktop_file_by_id(id)?;
+        let run_args = desktop_file
+            .get_run_args()
+            .context("Web app has no command to run")?;
+
+        debug!("Running web app: '{run_args:?}'");
+        HostCommand::from_args(&run_args)?.run_background()
+    }
+
+    /// Same as the app does when it starts
+    fn migrate(&self) -> Result<()> {
+        let mut migrated = Vec::new();
+        let mut failed = 0;
+
+        for mut desktop_file in self.get_owned_desktop_files() {
+            match desktop_file.migrate_to_snap() {
+                Ok(true) => migrated.push(WebAppOutput::from_desktop_file(&desktop_file)),
+                Ok(false) => {}
+                Err(error) => {
+                    error!(
+                        "Failed to move web app '{}' to snap browser: {error}",
+                        desktop_file.get_name().unwrap_or_default()
+                    );
+                    failed += 1;
+                }
+            }
+        }
+
+        if self.json {
+            Self::print_json(&migrated)?;
+        } else if migrated.is_empty() && failed == 0 {
+            println!("No web apps to migrate");
+        } else {
+            for web_app in &migrated {
+                println!(
+                    "Moved web app '{}' to {}",
+                    web_app.name,
+                    web_app.browser.as_deref().unwrap_or_default()
+                );
+                if let Some(profile) = &web_app.profile {
+                    println!("  Profile: {}", profile.display());
+                }
+            }
+        }
+
+        if failed > 0 {
+            bail!("Failed to migrate {failed} web app(s), see the log")
+        }
+        Ok(())
+    }
+
+    fn print_web_app(&self, desktop_file: &DesktopFile) -> Result<()> {
+        let web_app = WebAppOutput::from_desktop_file(desktop_file);
+
+        if self.json {
+            return Self::print_json(&web_app);
+        }
+
+        web_app.print_text();
+        Ok(())
+    }
+
+    fn print_json(value: &impl Serialize) -> Result<()> {
+        println!("{}", serde_json::to_string_pretty(value)?);
+        Ok(())
+    }
+
+    fn get_owned_desktop_files(&self) -> Vec<DesktopFile> {
+        debug!("Reading user desktop files");
+
+        utils::files::get_entries_in_dir(&self.app_dirs.applications())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|file| {
+                DesktopFile::from_path(&file.path(), &self.browser_configs, &self.app_dirs).ok()
+            })
+            .filter(DesktopFile::get_is_owned_app)
+            .collect()
+    }
+
+    fn get_desktop_file_by_id(&self, id: &str) -> Result<DesktopFile> {
+        self.get_owned_desktop_files()
+            .into_iter()
+            .find(|desktop_file| desktop_file.get_id().is_some_and(|app_id| app_id == id))
+            .context(format!("No web app found with id: '{id}'"))
+    }
+
+    fn get_installed_browser(&self, id: &str) -> Result<Rc<Browser>> {
+        let Some(browser) = self.browser_configs.get_by_id(id) else {
+            bail!("No installed browser found with id: '{id}', see the `browsers` command")
+        };
+        if !browser.is_installed() {
+            bail!("Browser is not installed: '{id}'")
+        }
+
+        Ok(browser)
+    }
+
+    fn set_icon(desktop_file: &mut DesktopFile, icon: &Path) -> Result<()> {
+        if !icon.is_file() {
+            bail!("Icon file does not exist: {}", icon.display())
+        }
+
+        let extension = icon
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        if extension != "png" && extension != "svg" {
+            bail!("Icon must be a png or svg file: {}", icon.display())
+        }
+
+        desktop_file.install_icon_from_file(icon)
+    }
+
+    /// Same flow as changing the isolate switch in the app
+    fn set_profile(desktop_file: &mut DesktopFile) -> Result<()> {
+        let is_isolated = desktop_file.get_isolated().unwrap_or(false);
+        let old_profile_path = desktop_file.get_profile_path().unwrap_or_default();
+
+        let new_profile_path = if is_isolated {
+            desktop_file
+                .build_profile_path()
+                .context("Could not set isolation")?
+        } else {
+            PathBuf::default()
+        };
+
+        if old_profile_path != new_profile_path && old_profile_path.is_dir() {
+            debug!(
+                path = old_profile_path.display().to_string(),
+                "Deleting profile"
+            );
+            desktop_file
+                .remove_profile(&old_profile_path)
+                .context("Failed to remove old profile")?;
+        }
+
+        desktop_file.set_profile_path(&new_profile_path);
+        Ok(())
+    }
+
+    fn validate_name(name: &str) -> Result<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("Name is empty")
+        }
+        Ok(name.to_string())
+    }
+
+    fn validate_url(url: &str) -> Result<String> {
+        if !Url::parse(url).is_ok_and(|url| url.is_web_url()) {
+            bail!("Please enter a valid URL (e.g., https://example.com)")
+        }
+        Ok(url.to_string())
+    }
+
+    /// `NAME=URL`, split on the first `=` as urls can contain one
+    fn validate_quick_links(quick_links: &[String]) -> Result<Vec<DesktopAction>> {
+        quick_links
+            .iter()
+            .map(|quick_link| {
+                let Some((name, url)) = quick_link.split_once('=') else {
+                    bail!("Quick link must be NAME=URL: {quick_link}")
+                };
+                Ok(DesktopAction {
+                    name: Self::validate_name(name)
+                        .context(format!("Invalid quick link: {quick_link}"))?,
+                    url: Self::validate_url(url.trim())
+                        .context(format!("Invalid quick link: {quick_link}"))?,
+                })
+            })
+            .collect()
+    }
+
+    fn validate_isolate(browser: &Browser, requested: Option<bool>, current: bool) -> Result<bool> {
+        match requested {
+            Some(true) if !browser.can_isolate => {
+                bail!("The selected browser is not capable of isolation")
+            }
+            Some(is_isolated) => Ok(is_isolated),
+            None => Ok(current && browser.can_isolate),
+        }
+    }
+
+    fn validate_maximize(
+        browser: &Browser,
+        requested: Option<bool>,
+        current: bool,
+    ) -> Result<bool> {
+        match requested {
+            Some(true) if !browser.can_start_maximized => {
+                bail!("The selected browser is not capable of starting maximized")
+            }
+            Some(is_maximized) => Ok(is_maximized),
+            None => Ok(current && browser.can_start_maximized),
+        }
+    }
+}
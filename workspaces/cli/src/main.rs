@@ -0,0 +1,169 @@
+mod commands;
+
+use anyhow::Result;
+use clap::{Args, Parser, Subcommand};
+use commands::Cli;
+use common::{
+    config::{self, OnceLockExt},
+    utils,
+};
+use std::path::PathBuf;
+use tracing::Level;
+use tracing_subscriber::{FmtSubscriber, util::SubscriberInitExt};
+
+#[derive(Parser)]
+#[command(about, long_about = None)]
+struct CliArgs {
+    /// Print machine readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List all web apps
+    List,
+    /// Show a single web app
+    Show {
+        /// Id of the web app
+        id: String,
+    },
+    /// List all supported browsers and their installation
+    Browsers,
+    /// Create a new web app
+    Create(CreateArgs),
+    /// Edit an existing web app
+    Edit(EditArgs),
+    /// Delete a web app, its icon and its isolated profile
+    Delete {
+        /// Id of the web app
+        id: String,
+    },
+    /// Open a web app
+    Run {
+        /// Id of the web app
+        id: String,
+    },
+    /// Move web apps of a system browser that now starts a snap to that snap,
+    /// with their isolated profile
+    Migrate,
+}
+
+#[derive(Args)]
+pub struct CreateArgs {
+    /// Name of the web app
+    #[arg(long)]
+    name: String,
+    /// Website URL (e.g., `https://example.com`)
+    #[arg(long)]
+    url: String,
+    /// Browser id, see the `browsers` command
+    #[arg(long)]
+    browser: String,
+    /// Icon file (png or svg), a letter icon of the name when left out
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    /// Link in the app's context menu, repeat for more
+    #[arg(long = "quick-link", value_name = "NAME=URL")]
+    quick_links: Vec<String>,
+    #[command(flatten)]
+    switches: SwitchArgs,
+}
+
+#[derive(Args)]
+pub struct EditArgs {
+    /// Id of the web app
+    id: String,
+    /// Name of the web app
+    #[arg(long)]
+    name: Option<String>,
+    /// Website URL (e.g., `https://example.com`)
+    #[arg(long)]
+    url: Option<String>,
+    /// Browser id, see the `browsers` command
+    #[arg(long)]
+    browser: Option<String>,
+    /// Icon file (png or svg)
+    #[arg(long)]
+    icon: Option<PathBuf>,
+    /// Link in the app's context menu, repeat for more. Replaces the current quick links.
+    #[arg(long = "quick-link", value_name = "NAME=URL")]
+    quick_links: Vec<String>,
+    /// Remove all quick links
+    #[arg(long, conflicts_with = "quick_links")]
+    no_quick_links: bool,
+    #[command(flatten)]
+    switches: SwitchArgs,
+}
+
+/// Same flags for create and edit, the last one given wins
+#[derive(Args)]
+#[allow(clippy::struct_excessive_bools)] // Flag pairs for clap
+pub struct SwitchArgs {
+    /// Use an isolated profile
+    #[arg(long, overrides_with = "no_isolate")]
+    isolate: bool,
+    /// Use the normal browser profile
+    #[arg(long, overrides_with = "isolate")]
+    no_isolate: bool,
+    /// Always start the app maximized
+    #[arg(long, overrides_with = "no_maximize")]
+    maximize: bool,
+    /// Start the app with the browser's window size
+    #[arg(long, overrides_with = "maximize")]
+    no_maximize: bool,
+}
+impl SwitchArgs {
+    /// `None` when neither flag is given
+    fn get_isolate(&self) -> Option<bool> {
+        Self::get_switch(self.isolate, self.no_isolate)
+    }
+
+    fn get_maximize(&self) -> Option<bool> {
+        Self::get_switch(self.maximize, self.no_maximize)
+    }
+
+    fn get_switch(on: bool, off: bool) -> Option<bool> {
+        match (on, off) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = CliArgs::parse();
+
+    config::init();
+
+    /* Logging */
+    let mut log_level = if cfg!(debug_assertions) {
+        Level::INFO
+    } else {
+        Level::WARN
+    };
+    log_level = utils::env::get_log_level().unwrap_or(log_level);
+    // Disable > info logging for external crates
+    let filter = format!(
+        "{}_cli={log_level},common={log_level}",
+        config::APP_NAME_UNDERSCORE.get_value()
+    );
+
+    // Logs go to stderr so stdout stays clean for scripts
+    let logger = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .finish();
+    logger.init();
+
+    config::log_all_values_debug();
+
+    let cli = Cli::new(args.json);
+    cli.init()?;
+    cli.run(args.command)
+}
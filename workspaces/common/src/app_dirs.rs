@@ -1,7 +1,11 @@
 use crate::config::{self, OnceLockExt};
 use anyhow::{Context, Result};
-use gtk::glib;
-use std::{cell::OnceCell, fs, path::PathBuf, rc::Rc};
+use std::{
+    cell::OnceCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 use tracing::debug;
 
 #[derive(Default)]
@@ -9,6 +13,8 @@ pub struct AppDirs {
     home: OnceCell<PathBuf>,
     data: OnceCell<PathBuf>,
     config: OnceCell<PathBuf>,
+    builtin_config: OnceCell<PathBuf>,
+    system_configs: OnceCell<Vec<PathBuf>>,
     system_data: OnceCell<Vec<PathBuf>>,
     user_data: OnceCell<PathBuf>,
     user_config: OnceCell<PathBuf>,
@@ -16,9 +22,12 @@ pub struct AppDirs {
     user_applications: OnceCell<PathBuf>,
     profiles: OnceCell<PathBuf>,
     icons: OnceCell<PathBuf>,
+    hicolor: OnceCell<PathBuf>,
     browser_configs: OnceCell<PathBuf>,
     browser_desktop_files: OnceCell<PathBuf>,
     flatpak: OnceCell<PathBuf>,
+    snap: OnceCell<PathBuf>,
+    fetch_cache: OnceCell<PathBuf>,
 }
 impl AppDirs {
     pub fn new() -> Rc<Self> {
@@ -32,10 +41,17 @@ impl AppDirs {
         let user_config = glib::user_config_dir();
         let app_config = user_config.join(config::APP_NAME_HYPHEN.get_value());
         let system_data = glib::system_data_dirs();
+        let builtin_config = app_data.join("config");
+        let system_configs = glib::system_config_dirs()
+            .iter()
+            .map(|path| path.join(config::APP_NAME_HYPHEN.get_value()))
+            .collect();
 
         let _ = self.home.set(home);
         let _ = self.data.set(app_data);
         let _ = self.config.set(app_config);
+        let _ = self.builtin_config.set(builtin_config);
+        let _ = self.system_configs.set(system_configs);
         let _ = self.system_data.set(system_data);
         let _ = self.user_data.set(user_data);
         let _ = self.user_config.set(user_config);
@@ -44,17 +60,23 @@ impl AppDirs {
         let applications = Self::build_applications_path()?;
         let profiles = self.build_profiles_path()?;
         let icons = self.build_icons_path()?;
+        let hicolor = Self::build_hicolor_path()?;
         let browser_configs = self.build_browser_configs_path()?;
         let browser_desktop_files = self.build_browser_desktop_files_path()?;
         let flatpak = self.build_flatpak_path();
+        let snap = self.build_snap_path();
+        let fetch_cache = Self::build_fetch_cache_path()?;
 
         let _ = self.system_icons.set(system_icons);
         let _ = self.user_applications.set(applications);
         let _ = self.profiles.set(profiles);
         let _ = self.icons.set(icons);
+        let _ = self.hicolor.set(hicolor);
         let _ = self.browser_configs.set(browser_configs);
         let _ = self.browser_desktop_files.set(browser_desktop_files);
         let _ = self.flatpak.set(flatpak);
+        let _ = self.snap.set(snap);
+        let _ = self.fetch_cache.set(fetch_cache);
 
         Ok(())
     }
@@ -71,6 +93,34 @@ impl AppDirs {
         self.config.get().unwrap().clone()
     }
 
+    /// Shipped config assets, overwritten on every start.
+    pub fn builtin_config(&self) -> PathBuf {
+        self.builtin_config.get().unwrap().clone()
+    }
+
+    /// System-wide overrides from `XDG_CONFIG_DIRS`, most important first.
+    pub fn system_configs(&self) -> Vec<PathBuf> {
+        self.system_configs.get().unwrap().clone()
+    }
+
+    /// All config dirs from lowest to highest priority:
+    /// built-in, system-wide and finally the user config dir.
+    pub fn config_layers(&self) -> Vec<PathBuf> {
+        let mut layers = vec![self.builtin_config()];
+        layers.extend(self.system_configs().into_iter().rev());
+        layers.push(self.config());
+        layers
+    }
+
+    /// Path of the highest priority layer that has this config file.
+    pub fn find_config_file(&self, relative_path: &Path) -> Option<PathBuf> {
+        self.config_layers()
+            .into_iter()
+            .rev()
+            .map(|layer| layer.join(relative_path))
+            .find(|path| path.is_file())
+    }
+
     pub fn system_data(&self) -> Vec<PathBuf> {
         self.system_data.get().unwrap().clone()
     }
@@ -95,6 +145,11 @@ impl AppDirs {
         self.icons.get().unwrap().clone()
     }
 
+    /// User icon theme where web app icons are installed
+    pub fn hicolor(&self) -> PathBuf {
+        self.hicolor.get().unwrap().clone()
+    }
+
     pub fn browser_configs(&self) -> PathBuf {
         self.browser_configs.get().unwrap().clone()
     }
@@ -107,6 +162,16 @@ impl AppDirs {
         self.flatpak.get().unwrap().clone()
     }
 
+    /// Data of snaps in home, snaps can't write outside of it
+    pub fn snap(&self) -> PathBuf {
+        self.snap.get().unwrap().clone()
+    }
+
+    /// Responses of online icon searches
+    pub fn fetch_cache(&self) -> PathBuf {
+        self.fetch_cache.get().unwrap().clone()
+    }
+
     fn build_system_icon_paths(&self) -> Vec<PathBuf> {
         let icons_dir_name = "icons";
         self.system_data()
@@ -166,6 +231,21 @@ impl AppDirs {
         Ok(icons_path)
     }
 
+    fn build_hicolor_path() -> Result<PathBuf> {
+        let hicolor_path = glib::user_data_dir().join("icons").join("hicolor");
+
+        debug!("Using hicolor icon theme path: {}", hicolor_path.display());
+
+        if !hicolor_path.is_dir() {
+            fs::create_dir_all(&hicolor_path).context(format!(
+                "Could not create hicolor icon theme dir: {}",
+                hicolor_path.display()
+            ))?;
+        }
+
+        Ok(hicolor_path)
+    }
+
     fn build_browser_configs_path(&self) -> Result<PathBuf> {
         let browsers_dir_name = "browsers";
         let browser_configs_path = self.config().join(browsers_dir_name);
@@ -201,6 +281,23 @@ impl AppDirs {
         Ok(browser_desktop_files_path)
     }
 
+    fn build_fetch_cache_path() -> Result<PathBuf> {
+        let fetch_cache_path = glib::user_cache_dir()
+            .join(config::APP_NAME_HYPHEN.get_value())
+            .join("fetch");
+
+        debug!("Using fetch cache path: {}", fetch_cache_path.display());
+
+        if !fetch_cache_path.is_dir() {
+            fs::create_dir_all(&fetch_cache_path).context(format!(
+                "Could not create fetch cache dir: {}",
+                fetch_cache_path.display()
+            ))?;
+        }
+
+        Ok(fetch_cache_path)
+    }
+
     fn build_flatpak_path(&self) -> PathBuf {
         let flatpak_path = self.home().join(".var").join("app");
 
@@ -208,4 +305,12 @@ impl AppDirs {
 
         flatpak_path
     }
+
+    fn build_snap_path(&self) -> PathBuf {
+        let snap_path = self.home().join("snap");
+
+        debug!("Using snap path: {}", snap_path.display());
+
+        snap_path
+    }
 }
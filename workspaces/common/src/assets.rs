@@ -5,8 +5,14 @@ use crate::{
 use anyhow::{Context, Result};
 use freedesktop_desktop_entry::DesktopEntry;
 use include_dir::{Dir, include_dir};
-use std::fs::{self};
-use tracing::{debug, info};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs::{self},
+    path::Path,
+};
+use tracing::{debug, error, info};
 
 // Calling extract on a subdir does not work and seems bugged.
 // Using indivudal imports.
@@ -17,23 +23,28 @@ static ICON_IN: &[u8] = include_bytes!("../../../assets/app-icon.png");
 static DESKTOP_FILE_IN: &str = include_str!("../../../assets/app.desktop");
 static META_INFO_IN: &str = include_str!("../../../assets/app.metainfo.xml");
 static APP_DESCRIPTION: &str = include_str!("../../../assets/app-description.markup");
+// Hashes of the configs shipped by previous releases, in `sha256sum` format
+static CONFIG_HASHES: &str = include_str!("../../../assets/config-hashes.txt");
 
 pub fn init(app_dirs: &AppDirs) -> Result<()> {
-    info!("Creating / overwriting assets");
+    info!("Creating / overwriting built-in assets");
     extract_config_dir(app_dirs)?;
+    remove_unmodified_user_configs(app_dirs);
     Ok(())
 }
 
+/// Only clears the user config layer, built-in and system-wide configs stay.
 pub fn reset_config_files(app_dirs: &AppDirs) -> Result<()> {
     let config_dir = app_dirs.config();
 
     if config_dir.is_dir() {
-        info!("Deleting config files");
-        fs::remove_dir_all(config_dir)?;
+        info!("Deleting user config files");
+        fs::remove_dir_all(&config_dir).context(format!(
+            "Failed to remove user config dir: {}",
+            config_dir.display()
+        ))?;
     }
 
-    extract_config_dir(app_dirs)?;
-
     Ok(())
 }
 
@@ -82,9 +93,34 @@ pub fn get_desktop_file_in() -> &'static str {
     DESKTOP_FILE_IN
 }
 
+/// The config hashes of previous releases with the current configs added, for the release tool
+pub fn get_config_hashes_with_current() -> String {
+    let mut hashes = get_config_hashes().into_iter().collect::<Vec<_>>();
+    hashes.sort_by(|(hash_a, path_a), (hash_b, path_b)| (path_a, hash_a).cmp(&(path_b, hash_b)));
+
+    hashes
+        .into_iter()
+        .fold(String::new(), |mut hashes, (hash, path)| {
+            let _ = writeln!(hashes, "{hash}  {path}");
+            hashes
+        })
+}
+
 fn extract_config_dir(app_dirs: &AppDirs) -> Result<()> {
     debug!("Extracting config dir");
-    let config_dir = app_dirs.config();
+    let config_dir = app_dirs.builtin_config();
+
+    // Start clean so configs removed from the assets don't linger
+    if config_dir.is_dir() {
+        fs::remove_dir_all(&config_dir).context(format!(
+            "Failed to remove built-in config dir: {}",
+            config_dir.display()
+        ))?;
+    }
+    fs::create_dir_all(&config_dir).context(format!(
+        "Failed to create built-in config dir: {}",
+        config_dir.display()
+    ))?;
 
     CONFIG.extract(&config_dir).context(format!(
         "Failed to extract config dir from ASSETS in: {}",
@@ -93,3 +129,74 @@ fn extract_config_dir(app_dirs: &AppDirs) -> Result<()> {
 
     Ok(())
 }
+
+fn hash_config(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .fold(String::new(), |mut hash, byte| {
+            let _ = write!(hash, "{byte:02x}");
+            hash
+        })
+}
+
+/// Pairs of hash and relative path, of previous releases and the current configs
+fn get_config_hashes() -> BTreeSet<(String, String)> {
+    fn add_dir(dir: &Dir, hashes: &mut BTreeSet<(String, String)>) {
+        for file in dir.files() {
+            hashes.insert((
+                hash_config(file.contents()),
+                file.path().to_string_lossy().to_string(),
+            ));
+        }
+        for sub_dir in dir.dirs() {
+            add_dir(sub_dir, hashes);
+        }
+    }
+
+    let mut hashes = CONFIG_HASHES
+        .lines()
+        .filter_map(|line| line.split_once("  "))
+        .map(|(hash, path)| (hash.to_string(), path.to_string()))
+        .collect();
+    add_dir(&CONFIG, &mut hashes);
+    hashes
+}
+
+/// Older versions extracted the assets into the user config dir.
+/// Untouched copies would shadow updated built-in configs, so remove them.
+/// A copy is untouched when it matches a config of the current or a previous release.
+fn remove_unmodified_user_configs(app_dirs: &AppDirs) {
+    let user_config_dir = app_dirs.config();
+    let hashes = get_config_hashes();
+    let paths = hashes.iter().map(|(_, path)| path).collect::<BTreeSet<_>>();
+
+    for relative_path in paths {
+        let path = user_config_dir.join(relative_path);
+        let Ok(contents) = fs::read(&path) else {
+            continue;
+        };
+        if !hashes.contains(&(hash_config(&contents), relative_path.clone())) {
+            continue;
+        }
+
+        debug!(
+            path = path.display().to_string(),
+            "Removing unmodified user config"
+        );
+        if let Err(error) = fs::remove_file(&path) {
+            error!(
+                error = error.to_string(),
+                "Failed to remove unmodified user config"
+            );
+            continue;
+        }
+
+        // Keep the top level dirs so users can find where to add configs.
+        // Fails when not empty, which is fine.
+        for dir in Path::new(relative_path).ancestors().skip(1) {
+            if dir.components().count() > 1 {
+                let _ = fs::remove_dir(user_config_dir.join(dir));
+            }
+        }
+    }
+}
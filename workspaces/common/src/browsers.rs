@@ -1,3 +1,6 @@
+mod detect;
+pub mod validate;
+
 use crate::utils;
 use crate::{
     app_dirs::AppDirs,
@@ -5,15 +8,25 @@ use crate::{
 };
 use anyhow::{Context, Result, bail};
 use freedesktop_desktop_entry::DesktopEntry;
-use gtk::{IconTheme, Image};
-use std::{cell::OnceCell, collections::HashSet, fs, path::Path, rc::Rc};
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+    rc::Rc,
+};
 use std::{fmt::Write as _, path::PathBuf};
 use tracing::{debug, error, info};
+use validate::{BrokenConfig, ConfigError};
 
 #[derive(PartialEq)]
 pub enum Installation {
     Flatpak,
+    /// Started from `/snap/bin`, confined like a flatpak
+    Snap,
     System,
+    /// An app image or a browser unpacked somewhere like `/opt`
+    Path,
     None,
 }
 
@@ -33,19 +46,104 @@ impl Base {
     }
 }
 
-#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct BrowserYaml {
-    name: String,
-    flatpak: Option<String>,
-    system_bin: Option<String>,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flatpak: Option<String>,
+    /// Snap name, like `firefox`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snap: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_bin: Option<String>,
+    /// Absolute or starting with `~/`, a `*` in the file name matches versions of app images
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub executable_paths: Vec<String>,
     #[serde(default)]
-    can_isolate: bool,
+    pub can_isolate: bool,
     #[serde(default)]
-    can_start_maximized: bool,
-    desktop_file_name_prefix: String,
-    base: String,
+    pub can_start_maximized: bool,
+    pub desktop_file_name_prefix: String,
+    pub base: String,
     #[serde(default)]
-    issues: Vec<String>,
+    pub issues: Vec<String>,
+}
+impl BrowserYaml {
+    /// Values of `base` the app knows about
+    pub const BASES: [&str; 3] = ["chromium", "firefox", "none"];
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some((_key, message)) = self.get_errors().into_iter().next() {
+            bail!(message)
+        }
+
+        Ok(())
+    }
+
+    /// Every invalid value with its key, these end up in commands, desktop files and file names
+    pub fn get_errors(&self) -> Vec<(&'static str, String)> {
+        let mut errors = Vec::new();
+
+        if self.name.trim().is_empty() {
+            errors.push(("name", "Name is empty".to_string()));
+        }
+        if self.flatpak.is_none()
+            && self.snap.is_none()
+            && self.system_bin.is_none()
+            && self.executable_paths.is_empty()
+        {
+            errors.push((
+                "flatpak",
+                "Needs a flatpak id, a snap, a system binary or an executable path".to_string(),
+            ));
+        }
+        if let Some(flatpak) = &self.flatpak
+            && !BrowserConfigs::is_valid_flatpak_id(flatpak)
+        {
+            errors.push(("flatpak", format!("Invalid flatpak id '{flatpak}'")));
+        }
+        if let Some(snap) = &self.snap
+            && !BrowserConfigs::is_valid_snap_name(snap)
+        {
+            errors.push(("snap", format!("Invalid snap name '{snap}'")));
+        }
+        if let Some(system_bin) = &self.system_bin
+            && !BrowserConfigs::is_valid_system_bin(system_bin)
+        {
+            errors.push(("system_bin", format!("Invalid system_bin '{system_bin}'")));
+        }
+        for executable_path in &self.executable_paths {
+            if !BrowserConfigs::is_valid_executable_path(executable_path) {
+                errors.push((
+                    "executable_paths",
+                    format!(
+                        "Invalid executable path '{executable_path}', it must be absolute or start with '~/' and only the file name can have a '*'"
+                    ),
+                ));
+            }
+        }
+        if !BrowserConfigs::is_valid_file_name(&self.desktop_file_name_prefix) {
+            errors.push((
+                "desktop_file_name_prefix",
+                format!(
+                    "Invalid desktop_file_name_prefix '{}'",
+                    self.desktop_file_name_prefix
+                ),
+            ));
+        }
+        if !Self::BASES.contains(&self.base.as_str()) {
+            errors.push((
+                "base",
+                format!(
+                    "Unknown base '{}', use one of: {}",
+                    self.base,
+                    Self::BASES.join(", ")
+                ),
+            ));
+        }
+
+        errors
+    }
 }
 
 struct BrowserConfig {
@@ -62,25 +160,27 @@ pub struct Browser {
     pub can_isolate: bool,
     pub can_start_maximized: bool,
     pub flatpak_id: Option<String>,
+    pub snap_name: Option<String>,
     pub executable: Option<String>,
+    /// The file found for a path installation
+    pub executable_path: Option<PathBuf>,
     pub desktop_file: DesktopEntry,
     pub desktop_file_name_prefix: String,
     pub base: Base,
     pub issues: Vec<String>,
     pub config_name: String,
+    pub icon_names: HashSet<String>,
+    pub icon_search_path: Option<PathBuf>,
+    /// Snaps point to a file instead of a themed icon
+    pub icon_file: Option<PathBuf>,
     configs: Rc<BrowserConfigs>,
-    icon_theme: Rc<IconTheme>,
-    icon_names: HashSet<String>,
     app_dirs: Rc<AppDirs>,
 }
 impl Browser {
-    const FALLBACK_IMAGE: &str = "web-browser-symbolic";
-
     fn new(
         browser_config: &BrowserConfig,
         installation: Installation,
         browser_configs: &Rc<BrowserConfigs>,
-        icon_theme: &Rc<IconTheme>,
         app_dirs: &Rc<AppDirs>,
     ) -> Self {
         let icon_names = Self::get_icon_names_from_config(browser_config);
@@ -88,6 +188,7 @@ impl Browser {
         let can_isolate = browser_config.config.can_isolate;
         let can_start_maximized = browser_config.config.can_start_maximized;
         let flatpak_id = browser_config.config.flatpak.clone();
+        let snap_name = browser_config.config.snap.clone();
         let executable = browser_config.config.system_bin.clone();
         let desktop_file = browser_config.desktop_file.clone();
         let desktop_file_name_prefix = browser_config.config.desktop_file_name_prefix.clone();
@@ -97,11 +198,20 @@ impl Browser {
 
         let id = match &installation {
             Installation::Flatpak => flatpak_id.clone().unwrap(),
+            // The snap command is often also the system binary
+            Installation::Snap => format!("{}-snap", snap_name.clone().unwrap()),
             Installation::System => executable.clone().unwrap(),
+            // The file changes with every app image update
+            Installation::Path => format!("{config_name}-path"),
             Installation::None => flatpak_id
                 .clone()
+                .or_else(|| {
+                    snap_name
+                        .as_ref()
+                        .map(|snap_name| format!("{snap_name}-snap"))
+                })
                 .or(executable.clone())
-                .expect("Could not create id for Browser"),
+                .unwrap_or_else(|| format!("{config_name}-path")),
         };
 
         Self {
@@ -111,27 +221,85 @@ impl Browser {
             can_isolate,
             can_start_maximized,
             flatpak_id,
+            snap_name,
             executable,
+            executable_path: None,
             desktop_file,
             desktop_file_name_prefix,
             config_name,
             configs: browser_configs.clone(),
             icon_names,
+            icon_search_path: None,
+            icon_file: None,
             base,
             issues,
-            icon_theme: icon_theme.clone(),
             app_dirs: app_dirs.clone(),
         }
     }
 
+    /// A browser that is not in the loaded configs, e.g. to preview a config being edited.
+    /// It is installed as flatpak when it has a flatpak id, then as snap, then as system browser.
+    pub fn from_yaml(
+        config_name: &str,
+        yaml: &BrowserYaml,
+        template: &str,
+        browser_configs: &Rc<BrowserConfigs>,
+        app_dirs: &Rc<AppDirs>,
+    ) -> Result<Self> {
+        yaml.validate()?;
+        let file_name = format!("{config_name}.yml");
+        let desktop_file = BrowserConfigs::parse_template(config_name, template, app_dirs)?;
+        let installation = if yaml.flatpak.is_some() {
+            Installation::Flatpak
+        } else if yaml.snap.is_some() {
+            Installation::Snap
+        } else if yaml.system_bin.is_some() {
+            Installation::System
+        } else {
+            Installation::Path
+        };
+
+        let browser_config = BrowserConfig {
+            config: yaml.clone(),
+            config_name: config_name.to_string(),
+            file_name,
+            desktop_file,
+        };
+
+        let mut browser = Self::new(&browser_config, installation, browser_configs, app_dirs);
+        if browser.installation == Installation::Path {
+            browser.executable_path = yaml
+                .executable_paths
+                .first()
+                .map(|path| detect::expand_home(path, &app_dirs.home()));
+        }
+
+        Ok(browser)
+    }
+
     pub fn is_flatpak(&self) -> bool {
         matches!(self.installation, Installation::Flatpak)
     }
 
+    pub fn is_snap(&self) -> bool {
+        matches!(self.installation, Installation::Snap)
+    }
+
     pub fn is_system(&self) -> bool {
         matches!(self.installation, Installation::System)
     }
 
+    pub fn is_path(&self) -> bool {
+        matches!(self.installation, Installation::Path)
+    }
+
+    pub fn is_app_image(&self) -> bool {
+        self.executable_path.as_ref().is_some_and(|path| {
+            path.extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("appimage"))
+        })
+    }
+
     pub fn is_installed(&self) -> bool {
         !matches!(self.installation, Installation::None)
     }
@@ -144,9 +312,18 @@ impl Browser {
             Installation::Flatpak => {
                 let _ = write!(txt, " (Flatpak)");
             }
+            Installation::Snap => {
+                let _ = write!(txt, " (Snap)");
+            }
             Installation::System => {
                 let _ = write!(txt, " (System)");
             }
+            Installation::Path if self.is_app_image() => {
+                let _ = write!(txt, " (AppImage)");
+            }
+            Installation::Path => {
+                let _ = write!(txt, " (Executable)");
+            }
             Installation::None => {}
         }
 
@@ -161,48 +338,25 @@ impl Browser {
                 };
                 Ok(format!("flatpak run {flatpak_id}"))
             }
+            Installation::Snap => {
+                let Some(snap_name) = &self.snap_name else {
+                    bail!("No snap name with snap installation")
+                };
+                Ok(detect::get_snap_command(snap_name))
+            }
             Installation::System => {
                 let Some(executable) = &self.executable else {
                     bail!("No executable with system installation")
                 };
                 Ok(executable.clone())
             }
-            Installation::None => bail!("Browser is not installed"),
-        }
-    }
-
-    pub fn get_icon(&self) -> Image {
-        for icon in &self.icon_names {
-            if !self.icon_theme.has_icon(icon) {
-                continue;
-            }
-            let image = Image::from_icon_name(icon);
-            if image.uses_fallback() {
-                continue;
-            }
-            return image;
-        }
-
-        Image::from_icon_name(Self::FALLBACK_IMAGE)
-    }
-
-    pub fn get_run_command(&self) -> Result<String> {
-        match self.installation {
-            Installation::Flatpak => {
-                let Some(flatpak_id) = self.flatpak_id.clone() else {
-                    bail!("No flatpak id on flatpak installation???")
-                };
-
-                let command = format!("flatpak run {flatpak_id}");
-                Ok(command)
-            }
-            Installation::System => {
-                let Some(executable) = self.executable.clone() else {
-                    bail!("No flatpak id on flatpak installation???")
+            Installation::Path => {
+                let Some(executable_path) = &self.executable_path else {
+                    bail!("No executable path with path installation")
                 };
-                Ok(executable)
+                Ok(executable_path.to_string_lossy().to_string())
             }
-            Installation::None => bail!("No installation type on 'Browser'"),
+            Installation::None => bail!("Browser is not installed"),
         }
     }
 
@@ -219,14 +373,14 @@ impl Browser {
 
         // Save in browser own location (for sandboxes)
         let browser_profile_path = || -> Result<PathBuf> {
-            let path = self
-                .app_dirs
-                .flatpak()
-                .join(&self.id)
-                .join("data")
-                .join(config::APP_NAME_HYPHEN.get_value())
-                .join("profiles");
-            Ok(path)
+            self.get_flatpak_profile_root()
+                .context("No flatpak id on flatpak installation")
+        };
+
+        // Snaps can only write in their own dir in home
+        let snap_profile_path = || -> Result<PathBuf> {
+            self.get_snap_profile_root()
+                .context("No snap name on snap installation")
         };
 
         let profile = match self.base {
@@ -236,13 +390,15 @@ impl Browser {
                never creates a default profile.
                Then there is --profile <path>, this works but will not create the path if it doesn't exists.
                So `--filesystem=~/.var/app:create` is needed to break in the sandbox to create the path if it doesn't exists.
+               The same goes for `--filesystem=~/snap` for snaps.
                All a bit poorly implemented.
 
                Chromium based just created the provided profile path
             */
             Base::Chromium | Base::Firefox => match self.installation {
                 Installation::Flatpak => browser_profile_path()?,
-                Installation::System => app_profile_path()?,
+                Installation::Snap => snap_profile_path()?,
+                Installation::System | Installation::Path => app_profile_path()?,
                 Installation::None => bail!("No installation type on 'Browser'"),
             },
 
@@ -254,6 +410,32 @@ impl Browser {
         Ok(profile)
     }
 
+    /// Profile location inside the flatpak sandbox of the browser
+    pub fn get_flatpak_profile_root(&self) -> Option<PathBuf> {
+        let flatpak_id = self.flatpak_id.as_ref()?;
+        let path = self
+            .app_dirs
+            .flatpak()
+            .join(flatpak_id)
+            .join("data")
+            .join(config::APP_NAME_HYPHEN.get_value())
+            .join("profiles");
+        Some(path)
+    }
+
+    /// Profile location in the dir of the snap that is kept between snap updates
+    pub fn get_snap_profile_root(&self) -> Option<PathBuf> {
+        let snap_name = self.snap_name.as_ref()?;
+        let path = self
+            .app_dirs
+            .snap()
+            .join(snap_name)
+            .join("common")
+            .join(config::APP_NAME_HYPHEN.get_value())
+            .join("profiles");
+        Some(path)
+    }
+
     pub fn get_index(&self) -> Option<usize> {
         self.configs.get_index(self)
     }
@@ -265,10 +447,28 @@ impl Browser {
             icon_names.insert(flatpak.trim().to_string());
         }
 
+        if let Some(snap) = &browser_config.config.snap {
+            icon_names.insert(snap.trim().to_string());
+        }
+
         if let Some(bin) = &browser_config.config.system_bin {
             icon_names.insert(bin.trim().to_string());
         }
 
+        // `~/Applications/firefox*.AppImage` is probably `firefox`
+        for executable_path in &browser_config.config.executable_paths {
+            let file_name = executable_path.rsplit('/').next().unwrap_or_default();
+            let icon_name = file_name
+                .split('*')
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches(".AppImage")
+                .trim_end_matches(['-', '_', '.']);
+            if !icon_name.is_empty() {
+                icon_names.insert(icon_name.to_string());
+            }
+        }
+
         icon_names.insert(browser_config.config.name.trim().to_string());
 
         icon_names
@@ -276,31 +476,144 @@ impl Browser {
 }
 
 pub struct BrowserConfigs {
-    all_browsers: OnceCell<Vec<Rc<Browser>>>,
-    uninstalled_browsers: OnceCell<Vec<Rc<Browser>>>,
-    icon_theme: Rc<IconTheme>,
+    configs: RefCell<Vec<Rc<BrowserConfig>>>,
+    /// Deployed files by flatpak id, `None` until detected
+    found_flatpaks: RefCell<Option<HashMap<String, PathBuf>>>,
+    /// Icon files by snap name, `None` until detected
+    found_snaps: RefCell<Option<HashMap<String, Option<PathBuf>>>>,
+    /// `None` until detected
+    /// Per found system binary, whether it only starts a snap
+    found_system_bins: RefCell<Option<HashMap<String, bool>>>,
+    /// Files by `executable_paths` pattern, `None` until detected
+    found_executable_paths: RefCell<Option<HashMap<String, PathBuf>>>,
+    all_browsers: RefCell<Vec<Rc<Browser>>>,
+    uninstalled_browsers: RefCell<Vec<Rc<Browser>>>,
+    broken_configs: RefCell<Vec<BrokenConfig>>,
+    /// Results of a detection started before the last `load` are dropped
+    load_count: Cell<u32>,
     app_dirs: Rc<AppDirs>,
 }
 impl BrowserConfigs {
-    pub fn new(icon_theme: &Rc<IconTheme>, app_dirs: &Rc<AppDirs>) -> Rc<Self> {
+    pub fn new(app_dirs: &Rc<AppDirs>) -> Rc<Self> {
         Rc::new(Self {
-            all_browsers: OnceCell::new(),
-            uninstalled_browsers: OnceCell::new(),
-            icon_theme: icon_theme.clone(),
+            configs: RefCell::new(Vec::new()),
+            found_flatpaks: RefCell::new(None),
+            found_snaps: RefCell::new(None),
+            found_system_bins: RefCell::new(None),
+            found_executable_paths: RefCell::new(None),
+            all_browsers: RefCell::new(Vec::new()),
+            uninstalled_browsers: RefCell::new(Vec::new()),
+            broken_configs: RefCell::new(Vec::new()),
+            load_count: Cell::new(0),
             app_dirs: app_dirs.clone(),
         })
     }
 
+    /// Loads the configs and detects the installed browsers before returning
     pub fn init(self: &Rc<Self>) {
-        self.set_browsers_from_files();
+        self.load();
+
+        let (flatpak_ids, snap_names, system_bins, executable_paths) = self.get_detect_candidates();
+        let installations = detect::get_flatpak_installations(&self.app_dirs);
+        self.set_found_flatpaks(detect::find_flatpaks(&flatpak_ids, &installations));
+        self.set_found_snaps(detect::find_snaps(&snap_names));
+        self.set_found_system_bins(detect::find_system_bins(&system_bins));
+        self.set_found_executable_paths(detect::find_executable_paths(
+            &executable_paths,
+            &self.app_dirs.home(),
+        ));
+    }
+
+    /// Only loads the configs, no browser is installed until `detect` has run.
+    /// Browsers handed out before keep their old config.
+    pub fn load(self: &Rc<Self>) {
+        self.load_count.set(self.load_count.get() + 1);
+        let (browser_configs, broken_configs) = self.get_browsers_from_files();
+
+        *self.configs.borrow_mut() = browser_configs;
+        *self.broken_configs.borrow_mut() = broken_configs;
+        *self.found_flatpaks.borrow_mut() = None;
+        *self.found_snaps.borrow_mut() = None;
+        *self.found_system_bins.borrow_mut() = None;
+        *self.found_executable_paths.borrow_mut() = None;
+        self.set_browsers();
+    }
+
+    /// Detects flatpak, snap, system and path browsers in parallel off the main thread,
+    /// `on_progress` runs every time browsers have been added
+    pub async fn detect<OnProgress>(self: &Rc<Self>, on_progress: OnProgress)
+    where
+        OnProgress: Fn(),
+    {
+        let load_count = self.load_count.get();
+        let (flatpak_ids, snap_names, system_bins, executable_paths) = self.get_detect_candidates();
+        let installations = detect::get_flatpak_installations(&self.app_dirs);
+        let home = self.app_dirs.home();
+
+        let flatpaks_handle =
+            gio::spawn_blocking(move || detect::find_flatpaks(&flatpak_ids, &installations));
+        let snaps_handle = gio::spawn_blocking(move || detect::find_snaps(&snap_names));
+        let system_bins_handle =
+            gio::spawn_blocking(move || detect::find_system_bins(&system_bins));
+        let executable_paths_handle =
+            gio::spawn_blocking(move || detect::find_executable_paths(&executable_paths, &home));
+
+        // Flatpaks are only a look in a few dirs, so usually first
+        let found_flatpaks = flatpaks_handle.await.unwrap_or_else(|_| {
+            error!("Flatpak browser detection failed");
+            HashMap::new()
+        });
+        if self.load_count.get() != load_count {
+            return;
+        }
+        self.set_found_flatpaks(found_flatpaks);
+        on_progress();
+
+        let found_snaps = snaps_handle.await.unwrap_or_else(|_| {
+            error!("Snap browser detection failed");
+            HashMap::new()
+        });
+        if self.load_count.get() != load_count {
+            return;
+        }
+        self.set_found_snaps(found_snaps);
+        on_progress();
+
+        let found_system_bins = system_bins_handle.await.unwrap_or_else(|_| {
+            error!("System browser detection failed");
+            HashMap::new()
+        });
+        if self.load_count.get() != load_count {
+            return;
+        }
+        self.set_found_system_bins(found_system_bins);
+        on_progress();
+
+        let found_executable_paths = executable_paths_handle.await.unwrap_or_else(|_| {
+            error!("Path browser detection failed");
+            HashMap::new()
+        });
+        if self.load_count.get() != load_count {
+            return;
+        }
+        self.set_found_executable_paths(found_executable_paths);
+        on_progress();
+    }
+
+    /// Until then browsers that are not found yet are left out of all lists
+    pub fn is_detecting(&self) -> bool {
+        self.found_flatpaks.borrow().is_none()
+            || self.found_snaps.borrow().is_none()
+            || self.found_system_bins.borrow().is_none()
+            || self.found_executable_paths.borrow().is_none()
     }
 
     pub fn get_all_browsers(&self) -> Vec<Rc<Browser>> {
-        self.all_browsers.get().unwrap().clone()
+        self.all_browsers.borrow().clone()
     }
 
     pub fn get_flatpak_browsers(&self) -> Vec<Rc<Browser>> {
-        let all_browsers_borrow = self.all_browsers.get().unwrap();
+        let all_browsers_borrow = self.all_browsers.borrow();
         all_browsers_borrow
             .iter()
             .filter(|browser| browser.is_flatpak())
@@ -308,8 +621,17 @@ impl BrowserConfigs {
             .collect()
     }
 
+    pub fn get_snap_browsers(&self) -> Vec<Rc<Browser>> {
+        let all_browsers_borrow = self.all_browsers.borrow();
+        all_browsers_borrow
+            .iter()
+            .filter(|browser| browser.is_snap())
+            .cloned()
+            .collect()
+    }
+
     pub fn get_system_browsers(&self) -> Vec<Rc<Browser>> {
-        let all_browsers_borrow = self.all_browsers.get().unwrap();
+        let all_browsers_borrow = self.all_browsers.borrow();
         all_browsers_borrow
             .iter()
             .filter(|browser| browser.installation == Installation::System)
@@ -317,35 +639,57 @@ impl BrowserConfigs {
             .collect()
     }
 
+    pub fn get_path_browsers(&self) -> Vec<Rc<Browser>> {
+        let all_browsers_borrow = self.all_browsers.borrow();
+        all_browsers_borrow
+            .iter()
+            .filter(|browser| browser.is_path())
+            .cloned()
+            .collect()
+    }
+
     pub fn get_uninstalled_browsers(&self) -> Vec<Rc<Browser>> {
-        self.uninstalled_browsers.get().unwrap().clone()
+        self.uninstalled_browsers.borrow().clone()
+    }
+
+    /// Configs that were left out, with the errors to fix
+    pub fn get_broken_configs(&self) -> Vec<BrokenConfig> {
+        self.broken_configs.borrow().clone()
     }
 
     pub fn get_by_id(&self, id: &str) -> Option<Rc<Browser>> {
         self.all_browsers
-            .get()
-            .unwrap()
+            .borrow()
             .iter()
             .find(|browser| browser.id == id)
             .cloned()
     }
 
-    pub fn get_index(&self, browser: &Browser) -> Option<usize> {
+    /// The snap that replaced the system browser with this id, its config has the same `system_bin`.
+    /// Only when the system binary starts that snap, a real system installation keeps its apps.
+    pub fn get_snap_by_system_bin(&self, system_bin: &str) -> Option<Rc<Browser>> {
+        let is_snap_wrapper = self
+            .found_system_bins
+            .borrow()
+            .as_ref()
+            .and_then(|found_system_bins| found_system_bins.get(system_bin).copied())
+            .unwrap_or(false);
+        if !is_snap_wrapper {
+            return None;
+        }
+
         self.all_browsers
-            .get()
-            .unwrap()
+            .borrow()
             .iter()
-            .position(|browser_iter| browser_iter.id == browser.id)
+            .find(|browser| browser.is_snap() && browser.executable.as_deref() == Some(system_bin))
+            .cloned()
     }
 
-    pub fn add_icon_search_path(self: &Rc<Self>, path: &Path) {
-        if !path.is_dir() {
-            debug!("Not a valid icon path: {}", path.display());
-            return;
-        }
-
-        debug!("Adding icon path to icon theme: {}", path.display());
-        self.icon_theme.add_search_path(path);
+    pub fn get_index(&self, browser: &Browser) -> Option<usize> {
+        self.all_browsers
+            .borrow()
+            .iter()
+            .position(|browser_iter| browser_iter.id == browser.id)
     }
 
     fn get_no_browser(self: &Rc<Self>) -> Browser {
@@ -356,89 +700,167 @@ impl BrowserConfigs {
             can_isolate: false,
             can_start_maximized: false,
             flatpak_id: None,
+            snap_name: None,
             executable: None,
+            executable_path: None,
             desktop_file: DesktopEntry::from_appid("No browser".to_string()),
             desktop_file_name_prefix: String::default(),
             config_name: String::default(),
             configs: self.clone(),
             icon_names: HashSet::from(["dialog-warning-symbolic".to_string()]),
+            icon_search_path: None,
+            icon_file: None,
             base: Base::None,
             issues: Vec::new(),
-            icon_theme: self.icon_theme.clone(),
             app_dirs: self.app_dirs.clone(),
         }
     }
 
-    fn set_browsers_from_files(self: &Rc<Self>) {
-        let browser_configs = self.get_browsers_from_files();
+    fn get_detect_candidates(&self) -> (Vec<String>, Vec<String>, Vec<String>, Vec<String>) {
+        let configs = self.configs.borrow();
+        let flatpak_ids = configs
+            .iter()
+            .filter_map(|browser_config| browser_config.config.flatpak.clone())
+            .collect();
+        let snap_names = configs
+            .iter()
+            .filter_map(|browser_config| browser_config.config.snap.clone())
+            .collect();
+        let system_bins = configs
+            .iter()
+            .filter_map(|browser_config| browser_config.config.system_bin.clone())
+            .collect();
+        let executable_paths = configs
+            .iter()
+            .flat_map(|browser_config| browser_config.config.executable_paths.clone())
+            .collect();
+
+        (flatpak_ids, snap_names, system_bins, executable_paths)
+    }
+
+    fn set_found_flatpaks(self: &Rc<Self>, found_flatpaks: HashMap<String, PathBuf>) {
+        *self.found_flatpaks.borrow_mut() = Some(found_flatpaks);
+        self.set_browsers();
+    }
+
+    fn set_found_snaps(self: &Rc<Self>, found_snaps: HashMap<String, Option<PathBuf>>) {
+        *self.found_snaps.borrow_mut() = Some(found_snaps);
+        self.set_browsers();
+    }
+
+    fn set_found_system_bins(self: &Rc<Self>, found_system_bins: HashMap<String, bool>) {
+        *self.found_system_bins.borrow_mut() = Some(found_system_bins);
+        self.set_browsers();
+    }
+
+    fn set_found_executable_paths(
+        self: &Rc<Self>,
+        found_executable_paths: HashMap<String, PathBuf>,
+    ) {
+        *self.found_executable_paths.borrow_mut() = Some(found_executable_paths);
+        self.set_browsers();
+    }
+
+    fn set_browsers(self: &Rc<Self>) {
+        let is_detecting = self.is_detecting();
+        let found_flatpaks = self.found_flatpaks.borrow();
+        let found_snaps = self.found_snaps.borrow();
+        let found_system_bins = self.found_system_bins.borrow();
+        let found_executable_paths = self.found_executable_paths.borrow();
         let mut installed_browsers = Vec::new();
         let mut uninstalled_browsers = Vec::new();
 
-        for browser_config in browser_configs {
+        for browser_config in self.configs.borrow().iter() {
             let mut is_installed = false;
 
-            if let Some(flatpak) = &browser_config.config.flatpak {
-                if Self::is_installed_flatpak(flatpak) {
-                    info!(
-                        "Found flatpak browser '{flatpak}' for config '{}'",
-                        browser_config.file_name
-                    );
-
-                    let browser = Rc::new(Browser::new(
-                        &browser_config,
-                        Installation::Flatpak,
-                        self,
-                        &self.icon_theme,
-                        &self.app_dirs,
-                    ));
-
-                    if utils::env::is_flatpak_container()
-                        && let Some(icon_search_path) = Self::get_icon_search_path_flatpak(flatpak)
-                    {
-                        self.add_icon_search_path(&icon_search_path);
-                    }
-
-                    installed_browsers.push(browser);
-                    is_installed = true;
-                } else {
-                    debug!(
-                        "Flatpak browser '{flatpak}' for '{}' is not installed",
-                        browser_config.file_name
-                    );
+            if let Some(flatpak) = &browser_config.config.flatpak
+                && let Some(location) = found_flatpaks
+                    .as_ref()
+                    .and_then(|found_flatpaks| found_flatpaks.get(flatpak))
+            {
+                debug!(
+                    "Found flatpak browser '{flatpak}' for config '{}'",
+                    browser_config.file_name
+                );
+
+                let mut browser =
+                    Browser::new(browser_config, Installation::Flatpak, self, &self.app_dirs);
+
+                if utils::env::is_flatpak_container() {
+                    browser.icon_search_path = Self::get_icon_search_path_flatpak(location);
                 }
+
+                installed_browsers.push(Rc::new(browser));
+                is_installed = true;
             }
 
-            if let Some(system_bin) = &browser_config.config.system_bin {
-                if Self::is_installed_system(system_bin) {
-                    info!(
-                        "Found system browser '{system_bin}' for config '{}'",
-                        browser_config.file_name
-                    );
-
-                    let browser = Rc::new(Browser::new(
-                        &browser_config,
-                        Installation::System,
-                        self,
-                        &self.icon_theme,
-                        &self.app_dirs,
-                    ));
-
-                    installed_browsers.push(browser);
-                    is_installed = true;
-                } else {
-                    debug!(
-                        "System browser '{system_bin}' for '{}' is not installed",
-                        browser_config.file_name
-                    );
-                }
+            let snap = browser_config.config.snap.as_ref().and_then(|snap| {
+                found_snaps
+                    .as_ref()
+                    .and_then(|found_snaps| found_snaps.get(snap))
+                    .map(|icon_file| (snap, icon_file))
+            });
+            if let Some((snap, icon_file)) = snap {
+                debug!(
+                    "Found snap browser '{snap}' for config '{}'",
+                    browser_config.file_name
+                );
+
+                let mut browser =
+                    Browser::new(browser_config, Installation::Snap, self, &self.app_dirs);
+                browser.icon_file.clone_from(icon_file);
+
+                installed_browsers.push(Rc::new(browser));
+                is_installed = true;
             }
 
-            if !is_installed {
+            let system_bin = found_system_bins.as_ref().and_then(|found_system_bins| {
+                Self::find_system_bin(browser_config, found_system_bins, snap.is_some())
+            });
+            if let Some(system_bin) = system_bin {
+                debug!(
+                    "Found system browser '{system_bin}' for config '{}'",
+                    browser_config.file_name
+                );
+
                 let browser = Rc::new(Browser::new(
-                    &browser_config,
+                    browser_config,
+                    Installation::System,
+                    self,
+                    &self.app_dirs,
+                ));
+
+                installed_browsers.push(browser);
+                is_installed = true;
+            }
+
+            // Packages often link the binary in `/opt` on `PATH`, that is the same browser
+            let executable_path = found_executable_paths
+                .as_ref()
+                .filter(|_| system_bin.is_none() && snap.is_none())
+                .and_then(|found_executable_paths| {
+                    Self::find_executable_path(browser_config, found_executable_paths)
+                });
+            if let Some(executable_path) = executable_path {
+                debug!(
+                    "Found executable '{}' for config '{}'",
+                    executable_path.display(),
+                    browser_config.file_name
+                );
+
+                let mut browser =
+                    Browser::new(browser_config, Installation::Path, self, &self.app_dirs);
+                browser.executable_path = Some(executable_path.clone());
+
+                installed_browsers.push(Rc::new(browser));
+                is_installed = true;
+            }
+
+            if !is_installed && !is_detecting {
+                let browser = Rc::new(Browser::new(
+                    browser_config,
                     Installation::None,
                     self,
-                    &self.icon_theme,
                     &self.app_dirs,
                 ));
                 uninstalled_browsers.push(browser);
@@ -448,86 +870,187 @@ impl BrowserConfigs {
         let no_browser = self.get_no_browser();
         installed_browsers.push(Rc::new(no_browser));
 
-        let _ = self.all_browsers.set(installed_browsers);
-        let _ = self.uninstalled_browsers.set(uninstalled_browsers);
+        *self.all_browsers.borrow_mut() = installed_browsers;
+        *self.uninstalled_browsers.borrow_mut() = uninstalled_browsers;
     }
 
-    fn is_installed_flatpak(flatpak: &str) -> bool {
-        let command = format!("flatpak info {flatpak}");
-        let result = utils::command::run_command_sync(&command);
+    /// A system binary that only starts the snap is the same browser as the snap,
+    /// a real system installation is listed next to it.
+    fn find_system_bin<'a>(
+        browser_config: &'a BrowserConfig,
+        found_system_bins: &HashMap<String, bool>,
+        has_snap: bool,
+    ) -> Option<&'a String> {
+        let system_bin = browser_config.config.system_bin.as_ref()?;
+        let is_snap_wrapper = found_system_bins.get(system_bin)?;
+
+        (!is_snap_wrapper || !has_snap).then_some(system_bin)
+    }
 
-        match result {
-            Err(error) => {
-                error!("Could not run command '{command}'. Error: {error:?}");
-                false
-            }
-            Ok(response) => response.success,
-        }
+    /// The first pattern that is found, they are in order of preference
+    fn find_executable_path<'a>(
+        browser_config: &BrowserConfig,
+        found_executable_paths: &'a HashMap<String, PathBuf>,
+    ) -> Option<&'a PathBuf> {
+        browser_config
+            .config
+            .executable_paths
+            .iter()
+            .find_map(|pattern| found_executable_paths.get(pattern))
     }
 
-    fn is_installed_system(system_bin: &str) -> bool {
-        let command = format!("which {system_bin}");
-        let result = utils::command::run_command_sync(&command);
+    fn is_valid_flatpak_id(flatpak: &str) -> bool {
+        !flatpak.is_empty()
+            && !flatpak.starts_with(['-', '.'])
+            && flatpak
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || matches!(char, '.' | '_' | '-'))
+    }
 
-        match result {
-            Err(error) => {
-                error!("Could not run command '{command}'. Error: {error:?}");
-                false
-            }
-            Ok(response) => response.success,
-        }
+    /// Also used for `desktop_file_name_prefix`, it is the start of the app file names
+    fn is_valid_file_name(file_name: &str) -> bool {
+        Self::is_valid_flatpak_id(file_name) && !file_name.ends_with('.')
+    }
+
+    /// The dir is looked in, so only the file name can match more than one file
+    fn is_valid_executable_path(executable_path: &str) -> bool {
+        let Some((dir, file_name)) = executable_path.rsplit_once('/') else {
+            return false;
+        };
+
+        (executable_path.starts_with('/') || executable_path.starts_with("~/"))
+            && !file_name.is_empty()
+            && !dir.contains('*')
+            && !executable_path.contains(char::is_control)
+    }
+
+    /// Lowercase letters, digits and hyphens like snapd allows
+    fn is_valid_snap_name(snap: &str) -> bool {
+        !snap.is_empty()
+            && !snap.starts_with('-')
+            && !snap.ends_with('-')
+            && snap
+                .chars()
+                .all(|char| char.is_ascii_lowercase() || char.is_ascii_digit() || char == '-')
     }
 
-    fn get_icon_search_path_flatpak(flatpak: &str) -> Option<PathBuf> {
-        if !utils::env::is_flatpak_container() {
-            error!("Don't need to get icon search path when not in flatpak container");
+    fn is_valid_system_bin(system_bin: &str) -> bool {
+        !system_bin.is_empty()
+            && !system_bin.starts_with('-')
+            && system_bin.chars().all(|char| {
+                char.is_ascii_alphanumeric() || matches!(char, '.' | '_' | '-' | '+' | '/')
+            })
+    }
+
+    /// Icons exported by the flatpak, the host icon theme is not visible in the sandbox
+    fn get_icon_search_path_flatpak(location: &Path) -> Option<PathBuf> {
+        let path = location.join("export").join("share").join("icons");
+
+        if !path.is_dir() {
+            error!("Invalid icon path: {}", path.display());
             return None;
         }
 
-        let command = format!("flatpak info --show-location {flatpak}");
-        let result = utils::command::run_command_sync(&command);
+        Some(path)
+    }
 
-        match result {
-            Err(error) => {
-                error!("Could not run command '{command}'. Error: {error:?}");
-                None
-            }
-            Ok(response) => {
-                if !response.success {
-                    error!(
-                        error = response.stderr,
-                        "Could not get icon search path for: {flatpak}"
-                    );
-                    return None;
-                }
+    /// The config and desktop file template from the highest layer that has them
+    pub fn read_config(&self, config_name: &str) -> Result<(BrowserYaml, String)> {
+        let yaml_path = self
+            .app_dirs
+            .find_config_file(&Path::new("browsers").join(format!("{config_name}.yml")))
+            .or_else(|| {
+                self.app_dirs
+                    .find_config_file(&Path::new("browsers").join(format!("{config_name}.yaml")))
+            })
+            .context(format!("No browser config found for: '{config_name}'"))?;
+        let template_path = self
+            .app_dirs
+            .find_config_file(&Path::new("desktop-files").join(format!("{config_name}.desktop")))
+            .context(format!("No desktop file found for: '{config_name}'"))?;
+
+        let yaml = serde_yaml::from_str(&fs::read_to_string(&yaml_path)?)
+            .context(format!("Failed to parse yml: '{}'", yaml_path.display()))?;
+        let template = fs::read_to_string(&template_path)?;
+
+        Ok((yaml, template))
+    }
 
-                let path = Path::new(&response.stdout)
-                    .join("export")
-                    .join("share")
-                    .join("icons");
+    /// Saves the config in the user config layer, where it replaces a config with the same
+    /// name of the other layers. Call `load` to use it.
+    pub fn save_user_config(
+        &self,
+        config_name: &str,
+        yaml: &BrowserYaml,
+        template: &str,
+    ) -> Result<()> {
+        if !Self::is_valid_config_name(config_name) {
+            bail!("Invalid config name '{config_name}'")
+        }
+        yaml.validate()?;
+        Self::parse_template(config_name, template, &self.app_dirs)?;
+
+        let browsers_dir = self.app_dirs.browser_configs();
+        let yaml_path = browsers_dir.join(format!("{config_name}.yml"));
+        let template_path = self
+            .app_dirs
+            .browser_desktop_files()
+            .join(format!("{config_name}.desktop"));
+
+        // A `.yaml` file would be loaded instead of the saved one
+        let yaml_alt_path = browsers_dir.join(format!("{config_name}.yaml"));
+        if yaml_alt_path.is_file() {
+            fs::remove_file(&yaml_alt_path)?;
+        }
 
-                if !path.is_dir() {
-                    error!("Invalid icon path for '{flatpak}': {}", path.display());
-                    return None;
-                }
+        info!("Saving browser config: '{}'", yaml_path.display());
+        fs::write(&yaml_path, serde_yaml::to_string(yaml)?)
+            .context(format!("Failed to save: '{}'", yaml_path.display()))?;
+        fs::write(&template_path, template)
+            .context(format!("Failed to save: '{}'", template_path.display()))?;
 
-                Some(path)
-            }
+        Ok(())
+    }
+
+    /// File names of configs, also the name of their desktop file template
+    pub fn is_valid_config_name(config_name: &str) -> bool {
+        !config_name.is_empty()
+            && !config_name.starts_with('-')
+            && config_name
+                .chars()
+                .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '-'))
+    }
+
+    /// Fails with the first error of the template
+    fn parse_template(
+        config_name: &str,
+        template: &str,
+        app_dirs: &AppDirs,
+    ) -> Result<DesktopEntry> {
+        let path = app_dirs
+            .browser_desktop_files()
+            .join(format!("{config_name}.desktop"));
+        if let Some(error) = validate::check_template(&path, template).first() {
+            bail!("{error}")
         }
+
+        DesktopEntry::from_str(&path, template, None::<&[String]>)
+            .context("Failed to parse desktop file template")
     }
 
-    fn get_browsers_from_files(&self) -> Vec<Rc<BrowserConfig>> {
+    fn get_browsers_from_files(&self) -> (Vec<Rc<BrowserConfig>>, Vec<BrokenConfig>) {
         debug!("Loading browsers config files");
 
         let mut browser_configs = Vec::new();
-        let browser_config_files =
-            utils::files::get_entries_in_dir(&self.app_dirs.browser_configs()).unwrap_or_default();
-
-        for file in &browser_config_files {
-            let file_name = file.file_name().to_string_lossy().to_string();
-            let file_path = file.path();
-            let Some(config_name) = file
-                .path()
+        let mut broken_configs = Vec::new();
+
+        for file_path in self.get_browser_config_files() {
+            let file_name = file_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let Some(config_name) = file_path
                 .file_stem()
                 .map(|stem| stem.to_string_lossy().to_string())
             else {
@@ -535,55 +1058,124 @@ impl BrowserConfigs {
                 continue;
             };
 
-            let extension = file_path.extension().unwrap_or_default().to_string_lossy();
-            debug!("Loading browser config: '{file_name}'");
+            debug!("Loading browser config: '{}'", file_path.display());
 
-            if extension != "yml" && extension != "yaml" {
-                debug!("Not a yml file: '{file_name}'");
-                continue;
+            match self.load_browser_config(&file_path, &config_name) {
+                Ok(browser_config) => browser_configs.push(Rc::new(browser_config)),
+                Err(errors) => {
+                    for error in &errors {
+                        error!("Broken browser config: {error}");
+                    }
+                    broken_configs.push(BrokenConfig {
+                        config_name,
+                        errors,
+                    });
+                }
             }
+        }
 
-            let Ok(file_string) = fs::read_to_string(&file_path) else {
-                error!("Failed to read to string: '{file_name}'");
-                continue;
-            };
-            let browser: BrowserYaml = match serde_yaml::from_str(&file_string) {
-                Ok(result) => result,
-                Err(error) => {
-                    error!("Failed to parse yml: '{file_name}'. Error: '{error:?}'");
-                    continue;
-                }
-            };
+        (browser_configs, broken_configs)
+    }
 
-            let desktop_file = match (|| -> Result<DesktopEntry> {
-                let desktop_file_path = self
-                    .app_dirs
-                    .browser_desktop_files()
-                    .join(
-                        file_path
-                            .file_stem()
-                            .context("Could not get the file stem")?,
-                    )
-                    .with_extension("desktop");
-                let desktop_file = DesktopEntry::from_path(&desktop_file_path, None::<&[String]>)?;
-                Ok(desktop_file)
-            })() {
-                Ok(result) => result,
-                Err(error) => {
-                    error!("Failed to parse .desktop file for: '{file_name}'. Error: '{error:?}'");
-                    continue;
+    /// Collects all errors of the config and its template instead of stopping at the first
+    fn load_browser_config(
+        &self,
+        file_path: &Path,
+        config_name: &str,
+    ) -> Result<BrowserConfig, Vec<ConfigError>> {
+        let file_name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let file_string = fs::read_to_string(file_path).map_err(|error| {
+            vec![ConfigError::new(
+                file_path,
+                1,
+                &format!("Failed to read: {error}"),
+            )]
+        })?;
+        let (browser, mut errors) = validate::check_yaml(file_path, &file_string);
+
+        let template_path = Path::new("desktop-files").join(format!("{config_name}.desktop"));
+        let desktop_file = match self.app_dirs.find_config_file(&template_path) {
+            None => {
+                errors.push(ConfigError::new(
+                    file_path,
+                    1,
+                    &format!(
+                        "No desktop file '{}' found in any config dir",
+                        template_path.display()
+                    ),
+                ));
+                None
+            }
+            Some(desktop_file_path) => match Self::load_template(&desktop_file_path) {
+                Ok(desktop_file) => Some(desktop_file),
+                Err(template_errors) => {
+                    errors.extend(template_errors);
+                    None
                 }
-            };
+            },
+        };
 
-            let browser_config = BrowserConfig {
+        match (browser, desktop_file) {
+            (Some(browser), Some(desktop_file)) if errors.is_empty() => Ok(BrowserConfig {
                 config: browser,
-                config_name,
+                config_name: config_name.to_string(),
                 file_name,
                 desktop_file,
-            };
-            browser_configs.push(Rc::new(browser_config));
+            }),
+            _ => Err(errors),
+        }
+    }
+
+    fn load_template(path: &Path) -> Result<DesktopEntry, Vec<ConfigError>> {
+        let template = fs::read_to_string(path).map_err(|error| {
+            vec![ConfigError::new(
+                path,
+                1,
+                &format!("Failed to read: {error}"),
+            )]
+        })?;
+
+        let errors = validate::check_template(path, &template);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        DesktopEntry::from_str(path, &template, None::<&[String]>).map_err(|error| {
+            vec![ConfigError::new(
+                path,
+                1,
+                &format!("Failed to parse: {error}"),
+            )]
+        })
+    }
+
+    /// Merges the browser configs of all config layers,
+    /// a config with the same name in a higher layer replaces the lower one.
+    fn get_browser_config_files(&self) -> Vec<PathBuf> {
+        let mut config_files = BTreeMap::new();
+
+        for layer in self.app_dirs.config_layers() {
+            let browsers_dir = layer.join("browsers");
+            for file in utils::files::get_entries_in_dir(&browsers_dir).unwrap_or_default() {
+                let file_path = file.path();
+                let extension = file_path.extension().unwrap_or_default();
+                if extension != "yml" && extension != "yaml" {
+                    debug!("Not a yml file: '{}'", file_path.display());
+                    continue;
+                }
+                let Some(config_name) = file_path.file_stem() else {
+                    continue;
+                };
+
+                debug!("Found browser config: '{}'", file_path.display());
+                config_files.insert(config_name.to_os_string(), file_path);
+            }
         }
 
-        browser_configs
+        config_files.into_values().collect()
     }
 }
@@ -0,0 +1,378 @@
+use crate::{
+    app_dirs::AppDirs,
+    utils::{self, command::HostCommand},
+};
+use std::{
+    collections::HashMap,
+    env, fs,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use tracing::{debug, error};
+
+const SNAP_BIN_DIR: &str = "/snap/bin";
+/// What the commands in [`SNAP_BIN_DIR`] link to
+const SNAP_COMMAND: &str = "/usr/bin/snap";
+/// Scripts that start a snap are small, like `exec /snap/bin/firefox "$@"`
+const MAX_WRAPPER_SIZE: u64 = 16 * 1024;
+/// Desktop files snapd exports for the installed snaps
+const SNAP_APPLICATIONS_DIR: &str = "/var/lib/snapd/desktop/applications";
+
+/// Flatpak installations to look in, the user installation first like `flatpak run` does.
+/// Inside the sandbox custom installations are only found with access to the host `/etc`.
+pub fn get_flatpak_installations(app_dirs: &AppDirs) -> Vec<PathBuf> {
+    let user_data = if utils::env::is_flatpak_container() {
+        env::var_os("HOST_XDG_DATA_HOME")
+            .map_or_else(|| app_dirs.home().join(".local/share"), PathBuf::from)
+    } else {
+        app_dirs.user_data()
+    };
+    let system = env::var_os("FLATPAK_SYSTEM_DIR")
+        .map_or_else(|| PathBuf::from("/var/lib/flatpak"), PathBuf::from);
+
+    let mut installations = vec![user_data.join("flatpak"), system];
+    for config_dir in [
+        "/etc/flatpak/installations.d",
+        "/run/host/etc/flatpak/installations.d",
+    ] {
+        installations.extend(get_custom_installations(Path::new(config_dir)));
+    }
+
+    installations
+}
+
+/// The deployed files of every flatpak id that is installed
+pub fn find_flatpaks(
+    flatpak_ids: &[String],
+    installations: &[PathBuf],
+) -> HashMap<String, PathBuf> {
+    let mut found = HashMap::new();
+
+    for flatpak_id in flatpak_ids {
+        let location = installations
+            .iter()
+            .map(|installation| {
+                installation
+                    .join("app")
+                    .join(flatpak_id)
+                    .join("current")
+                    .join("active")
+            })
+            .find(|location| location.is_dir());
+
+        let Some(location) = location else {
+            debug!("Flatpak '{flatpak_id}' is not installed");
+            continue;
+        };
+        debug!("Found flatpak '{flatpak_id}' in: {}", location.display());
+        found.insert(flatpak_id.clone(), location);
+    }
+
+    found
+}
+
+/// The icon file of every snap that is installed, if snapd exported one.
+/// From inside the sandbox the commands are listed on the host, they link to `/usr/bin/snap`.
+pub fn find_snaps(snap_names: &[String]) -> HashMap<String, Option<PathBuf>> {
+    if snap_names.is_empty() {
+        return HashMap::new();
+    }
+
+    let snap_bin_dir = PathBuf::from(SNAP_BIN_DIR);
+    let executables = if utils::env::is_flatpak_container() {
+        list_executables_on_host(&[snap_bin_dir])
+    } else {
+        list_executables(&snap_bin_dir)
+    };
+
+    let mut found = HashMap::new();
+    for snap_name in snap_names {
+        let command = PathBuf::from(get_snap_command(snap_name));
+        if !executables.contains(&command) {
+            debug!("Snap '{snap_name}' is not installed");
+            continue;
+        }
+        debug!("Found snap '{snap_name}'");
+        found.insert(snap_name.clone(), get_snap_icon(snap_name));
+    }
+
+    found
+}
+
+pub fn get_snap_command(snap_name: &str) -> String {
+    format!("{SNAP_BIN_DIR}/{snap_name}")
+}
+
+/// `Icon` of the exported desktop file, snaps ship an icon file instead of a themed icon
+fn get_snap_icon(snap_name: &str) -> Option<PathBuf> {
+    // Desktop files are named `<snap>_<app>.desktop`, the main app has the name of the snap
+    let main_file_name = format!("{snap_name}_{snap_name}.desktop");
+    let mut desktop_files: Vec<PathBuf> =
+        utils::files::get_entries_in_dir(Path::new(SNAP_APPLICATIONS_DIR))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                file_name.starts_with(&format!("{snap_name}_")) && file_name.ends_with(".desktop")
+            })
+            .collect();
+    desktop_files.sort_by_key(|path| path.file_name() != Some(main_file_name.as_ref()));
+
+    desktop_files.iter().find_map(|desktop_file| {
+        let contents = fs::read_to_string(desktop_file).ok()?;
+        let icon = contents.lines().find_map(|line| {
+            line.split_once('=')
+                .filter(|(key, _)| key.trim() == "Icon")
+                .map(|(_, value)| PathBuf::from(value.trim()))
+        })?;
+        if !icon.is_absolute() || !icon.is_file() {
+            debug!(
+                "No icon file for snap '{snap_name}' in: {}",
+                desktop_file.display()
+            );
+            return None;
+        }
+        Some(icon)
+    })
+}
+
+/// Looks in `PATH`, from inside the sandbox a single command on the host does that.
+/// The value is `true` when the command only starts a snap, see [`is_snap_wrapper`].
+pub fn find_system_bins(system_bins: &[String]) -> HashMap<String, bool> {
+    if utils::env::is_flatpak_container() {
+        return find_system_bins_on_host(system_bins);
+    }
+
+    let path_dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+
+    system_bins
+        .iter()
+        .filter_map(|system_bin| {
+            let path = if system_bin.contains('/') {
+                Some(PathBuf::from(system_bin)).filter(|path| is_executable(path))
+            } else {
+                path_dirs
+                    .iter()
+                    .map(|dir| dir.join(system_bin))
+                    .find(|path| is_executable(path))
+            }?;
+            let is_snap_wrapper = is_snap_wrapper(&path);
+            if is_snap_wrapper {
+                debug!("System binary '{system_bin}' starts a snap");
+            }
+            Some((system_bin.clone(), is_snap_wrapper))
+        })
+        .collect()
+}
+
+fn find_system_bins_on_host(system_bins: &[String]) -> HashMap<String, bool> {
+    if system_bins.is_empty() {
+        return HashMap::new();
+    }
+
+    // Same checks as `is_snap_wrapper`, prints `snap <bin>` or `system <bin>` per found one
+    let command = HostCommand::new("sh")
+        .arg("-c")
+        .arg(&format!(
+            r#"for bin; do
+                path=$(command -v "$bin") || continue
+                resolved=$(readlink -f "$path")
+                case "$resolved" in
+                    {SNAP_BIN_DIR}/*|/snap/*|{SNAP_COMMAND}) echo "snap $bin"; continue ;;
+                esac
+                if [ "$(head -c 2 "$resolved")" = '#!' ] \
+                    && [ "$(wc -c < "$resolved")" -le {MAX_WRAPPER_SIZE} ] \
+                    && grep -qE '{SNAP_BIN_DIR}/|snap run' "$resolved"; then
+                    echo "snap $bin"
+                else
+                    echo "system $bin"
+                fi
+            done"#
+        ))
+        .arg("sh")
+        .args(system_bins);
+    let response = match command.run_sync() {
+        Ok(response) => response,
+        Err(error) => {
+            error!("Could not run command '{command}'. Error: {error:?}");
+            return HashMap::new();
+        }
+    };
+
+    response
+        .stdout
+        .lines()
+        .filter_map(|line| line.trim().split_once(' '))
+        .filter(|(_, system_bin)| system_bins.iter().any(|bin| bin == system_bin))
+        .map(|(kind, system_bin)| (system_bin.to_string(), kind == "snap"))
+        .collect()
+}
+
+/// A command in `/snap/bin`, `snap` itself, or a script that starts a snap like the
+/// transitional packages of Ubuntu. It can't use the profiles of a system browser.
+fn is_snap_wrapper(path: &Path) -> bool {
+    let resolved = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if resolved.starts_with("/snap") || resolved == Path::new(SNAP_COMMAND) {
+        return true;
+    }
+
+    let is_small = fs::metadata(&resolved).is_ok_and(|metadata| metadata.len() <= MAX_WRAPPER_SIZE);
+    if !is_small {
+        return false;
+    }
+    fs::read(&resolved).is_ok_and(|contents| {
+        let script = String::from_utf8_lossy(&contents);
+        script.starts_with("#!")
+            && (script.contains(&format!("{SNAP_BIN_DIR}/")) || script.contains("snap run"))
+    })
+}
+
+/// The file of every `executable_paths` pattern that is found. A `*` in the file name matches
+/// anything, the last match in natural order wins so the newest version of an app image is used.
+pub fn find_executable_paths(patterns: &[String], home: &Path) -> HashMap<String, PathBuf> {
+    let expanded_patterns: Vec<(&String, PathBuf)> = patterns
+        .iter()
+        .map(|pattern| (pattern, expand_home(pattern, home)))
+        .collect();
+    let mut dirs: Vec<PathBuf> = expanded_patterns
+        .iter()
+        .filter_map(|(_, path)| path.parent().map(Path::to_path_buf))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+
+    let executables = if utils::env::is_flatpak_container() {
+        list_executables_on_host(&dirs)
+    } else {
+        dirs.iter().flat_map(|dir| list_executables(dir)).collect()
+    };
+
+    let mut found = HashMap::new();
+    for (pattern, path) in expanded_patterns {
+        let file_name_pattern = path.file_name().unwrap_or_default().to_string_lossy();
+        let executable = executables
+            .iter()
+            .filter(|executable| executable.parent() == path.parent())
+            .filter(|executable| {
+                is_file_name_match(
+                    &file_name_pattern,
+                    &executable.file_name().unwrap_or_default().to_string_lossy(),
+                )
+            })
+            .max_by(|a, b| {
+                utils::strings::compare_natural(&a.to_string_lossy(), &b.to_string_lossy())
+            });
+
+        let Some(executable) = executable else {
+            debug!("No executable found for '{pattern}'");
+            continue;
+        };
+        debug!("Found executable for '{pattern}': {}", executable.display());
+        found.insert(pattern.clone(), executable.clone());
+    }
+
+    found
+}
+
+/// Paths in configs can start with `~/`
+pub fn expand_home(path: &str, home: &Path) -> PathBuf {
+    path.strip_prefix("~/")
+        .map_or_else(|| PathBuf::from(path), |rest| home.join(rest))
+}
+
+/// Case insensitive, app images are not named consistently
+fn is_file_name_match(pattern: &str, file_name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let file_name = file_name.to_lowercase();
+    let mut parts = pattern.split('*');
+
+    let Some(mut rest) = file_name.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(index) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[index + part.len()..];
+    }
+
+    rest.ends_with(last)
+}
+
+fn list_executables(dir: &Path) -> Vec<PathBuf> {
+    utils::files::get_entries_in_dir(dir)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| entry.path())
+        .filter(|path| is_executable(path))
+        .collect()
+}
+
+/// `/opt` and the home dir are not visible in the sandbox
+fn list_executables_on_host(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    if dirs.is_empty() {
+        return Vec::new();
+    }
+
+    // Missing dirs only print to stderr
+    let command = HostCommand::new("sh")
+        .arg("-c")
+        .arg(r#"for dir; do find -L "$dir" -maxdepth 1 -type f -perm -u+x; done"#)
+        .arg("sh")
+        .args(
+            &dirs
+                .iter()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .collect::<Vec<_>>(),
+        );
+    match command.run_sync() {
+        Ok(response) => response.stdout.lines().map(PathBuf::from).collect(),
+        Err(error) => {
+            error!("Could not run command '{command}'. Error: {error:?}");
+            Vec::new()
+        }
+    }
+}
+
+fn is_executable(path: &Path) -> bool {
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// `Path=` of the `[Installation "<name>"]` groups in `installations.d`
+fn get_custom_installations(config_dir: &Path) -> Vec<PathBuf> {
+    let mut installations = Vec::new();
+
+    for file in utils::files::get_entries_in_dir(config_dir).unwrap_or_default() {
+        let file_path = file.path();
+        if file_path.extension().unwrap_or_default() != "conf" {
+            continue;
+        }
+        let Ok(config) = fs::read_to_string(&file_path) else {
+            error!("Failed to read: '{}'", file_path.display());
+            continue;
+        };
+
+        for line in config.lines() {
+            if let Some((key, value)) = line.split_once('=')
+                && key.trim() == "Path"
+            {
+                debug!(
+                    "Found custom flatpak installation '{}' in: {}",
+                    value.trim(),
+                    file_path.display()
+                );
+                installations.push(PathBuf::from(value.trim()));
+            }
+        }
+    }
+
+    installations
+}
@@ -0,0 +1,209 @@
+use super::BrowserYaml;
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+/// A mistake in a browser config file, `line` starts at 1
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+impl ConfigError {
+    pub fn new(path: &Path, line: usize, message: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            line,
+            message: message.to_string(),
+        }
+    }
+}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let file_name = self.path.file_name().unwrap_or_default().to_string_lossy();
+        write!(f, "{file_name}:{}: {}", self.line, self.message)
+    }
+}
+
+/// A browser config that could not be loaded, with everything that is wrong with it
+#[derive(Debug, Clone)]
+pub struct BrokenConfig {
+    pub config_name: String,
+    pub errors: Vec<ConfigError>,
+}
+
+const KNOWN_KEYS: [&str; 10] = [
+    "name",
+    "flatpak",
+    "snap",
+    "system_bin",
+    "executable_paths",
+    "can_isolate",
+    "can_start_maximized",
+    "desktop_file_name_prefix",
+    "base",
+    "issues",
+];
+/// Filled in by `DesktopFile::fill_browser_template`
+const TEMPLATE_VARIABLES: [&str; 7] = [
+    "command",
+    "name",
+    "url",
+    "domain",
+    "domain_path",
+    "icon",
+    "app_id",
+];
+const TEMPLATE_CONDITIONALS: [&str; 2] = ["is_isolated", "is_maximized"];
+
+/// The config is only returned when it can be parsed, the values can still have errors
+pub fn check_yaml(path: &Path, text: &str) -> (Option<BrowserYaml>, Vec<ConfigError>) {
+    let mut errors = Vec::new();
+
+    let mapping: serde_yaml::Mapping = match serde_yaml::from_str(text) {
+        Ok(mapping) => mapping,
+        Err(error) => {
+            errors.push(ConfigError::new(
+                path,
+                get_error_line(&error),
+                &format!("Invalid yml: {error}"),
+            ));
+            return (None, errors);
+        }
+    };
+
+    for key in mapping.keys() {
+        match key.as_str() {
+            Some(key) if KNOWN_KEYS.contains(&key) => {}
+            Some(key) => errors.push(ConfigError::new(
+                path,
+                get_key_line(text, key),
+                &format!("Unknown key '{key}'"),
+            )),
+            None => errors.push(ConfigError::new(path, 1, "Keys must be text")),
+        }
+    }
+
+    let yaml: BrowserYaml = match serde_yaml::from_str(text) {
+        Ok(yaml) => yaml,
+        Err(error) => {
+            errors.push(ConfigError::new(
+                path,
+                get_error_line(&error),
+                &error.to_string(),
+            ));
+            return (None, errors);
+        }
+    };
+
+    for (key, message) in yaml.get_errors() {
+        errors.push(ConfigError::new(path, get_key_line(text, key), &message));
+    }
+
+    errors.sort_by_key(|error| error.line);
+    (Some(yaml), errors)
+}
+
+/// Checks the placeholders of every line and the `Exec` of `[Desktop Entry]`
+pub fn check_template(path: &Path, text: &str) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut group = "";
+    let mut exec = None;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let trimmed = line.trim();
+
+        if let Some(name) = trimmed
+            .strip_prefix('[')
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            group = name;
+            continue;
+        }
+        if group == "Desktop Entry"
+            && exec.is_none()
+            && trimmed
+                .split_once('=')
+                .is_some_and(|(key, _)| key.trim() == "Exec")
+        {
+            exec = Some((line_number, trimmed));
+        }
+
+        let mut rest = line;
+        while let Some(start) = rest.find("%{") {
+            let after = &rest[start + 2..];
+            let Some(end) = after.find('}') else {
+                errors.push(ConfigError::new(path, line_number, "Unclosed '%{'"));
+                break;
+            };
+            if let Some(message) = check_placeholder(&after[..end]) {
+                errors.push(ConfigError::new(path, line_number, &message));
+            }
+            rest = &after[end + 1..];
+        }
+    }
+
+    match exec {
+        None => errors.push(ConfigError::new(path, 1, "No Exec in [Desktop Entry]")),
+        Some((line_number, exec)) => {
+            for variable in ["command", "url"] {
+                if !exec.contains(&format!("%{{{variable}}}")) {
+                    errors.push(ConfigError::new(
+                        path,
+                        line_number,
+                        &format!("Exec has no '%{{{variable}}}'"),
+                    ));
+                }
+            }
+        }
+    }
+
+    errors.sort_by_key(|error| error.line);
+    errors
+}
+
+/// The text between `%{` and `}`, variables must match exactly
+fn check_placeholder(placeholder: &str) -> Option<String> {
+    if let Some((key, value)) = placeholder.split_once('?') {
+        let key = key.trim();
+        if TEMPLATE_VARIABLES.contains(&key) {
+            return Some(format!("'%{{{key}}}' is not a conditional"));
+        }
+        if !TEMPLATE_CONDITIONALS.contains(&key) {
+            return Some(format!("Unknown conditional '{key}'"));
+        }
+        if value.trim().is_empty() {
+            return Some(format!("Conditional '{key}' has nothing after '?'"));
+        }
+        return None;
+    }
+
+    if TEMPLATE_VARIABLES.contains(&placeholder) {
+        return None;
+    }
+    let key = placeholder.trim();
+    if TEMPLATE_CONDITIONALS.contains(&key) {
+        return Some(format!(
+            "Conditional '{key}' needs a value, like '%{{{key} ? --some-arg}}'"
+        ));
+    }
+
+    Some(format!("Unknown variable '%{{{placeholder}}}'"))
+}
+
+/// Only finds top level keys, that is all a browser config has
+fn get_key_line(text: &str, key: &str) -> usize {
+    text.lines()
+        .position(|line| {
+            line.strip_prefix(key)
+                .is_some_and(|rest| rest.trim_start().starts_with(':'))
+        })
+        .map_or(1, |index| index + 1)
+}
+
+fn get_error_line(error: &serde_yaml::Error) -> usize {
+    error.location().map_or(1, |location| location.line())
+}
@@ -1,11 +1,16 @@
+mod escape;
+mod icon_theme;
+
 use crate::{
     app_dirs::AppDirs,
     browsers::{Base, Browser, BrowserConfigs},
     config::{self, OnceLockExt},
+    url::UrlExt,
+    utils,
 };
-use anyhow::{Context, Result, bail};
-use freedesktop_desktop_entry::DesktopEntry;
-use gtk::{Image, gdk_pixbuf::Pixbuf, prelude::WidgetExt};
+use anyhow::{Context, Result, anyhow, bail};
+use freedesktop_desktop_entry::{DesktopEntry, Group, LocaleMap};
+use gdk_pixbuf::{Pixbuf, PixbufLoader, prelude::PixbufLoaderExt};
 use rand::{Rng, distributions::Alphanumeric};
 use regex::Regex;
 use semver::Version;
@@ -18,8 +23,11 @@ use std::{
 use tracing::{debug, error, info};
 use url::Url;
 
-pub struct Icon {
-    pub pixbuf: Pixbuf,
+/// A quick link in the app's context menu, a `[Desktop Action]` group in the file
+#[derive(Debug, PartialEq, Clone)]
+pub struct DesktopAction {
+    pub name: String,
+    pub url: String,
 }
 
 pub struct DesktopFileEntries {
@@ -29,11 +37,13 @@ pub struct DesktopFileEntries {
     browser: Rc<Browser>,
     url: String,
     url_path: String,
+    host: String,
     domain: String,
     isolate: bool,
     maximize: bool,
     icon_path: PathBuf,
     profile_path: PathBuf,
+    actions: Vec<DesktopAction>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -47,8 +57,10 @@ pub enum Keys {
     Maximize,
     Profile,
     Name,
+    Comment,
     Exec,
     Icon,
+    Actions,
     StartupWMClass,
 }
 impl Display for Keys {
@@ -65,8 +77,10 @@ impl Display for Keys {
             Self::Maximize => write!(f, "X-{}-MAXIMIZE", &identifier),
             Self::Profile => write!(f, "X-{}-PROFILE", &identifier),
             Self::Name => write!(f, "Name"),
+            Self::Comment => write!(f, "Comment"),
             Self::Exec => write!(f, "Exec"),
             Self::Icon => write!(f, "Icon"),
+            Self::Actions => write!(f, "Actions"),
             Self::StartupWMClass => write!(f, "StartupWMClass"),
         }
     }
@@ -143,6 +157,8 @@ pub struct DesktopFile {
     app_dirs: Rc<AppDirs>,
 }
 impl DesktopFile {
+    const ACTION_GROUP_PREFIX: &str = "Desktop Action ";
+
     pub fn new(browser_configs: &Rc<BrowserConfigs>, app_dirs: &Rc<AppDirs>) -> Self {
         let mut desktop_entry = DesktopEntry::from_appid(String::new());
 
@@ -271,6 +287,14 @@ impl DesktopFile {
             .and_then(map_to_string_option)
     }
 
+    /// The `Exec` value split into the program and its arguments
+    pub fn get_run_args(&self) -> Option<Vec<String>> {
+        let exec = self.get_exec()?;
+        escape::parse_exec(&exec)
+            .inspect_err(|error| error!(error = error.to_string(), "Failed to parse Exec"))
+            .ok()
+    }
+
     pub fn get_id(&self) -> Option<String> {
         self.desktop_entry
             .desktop_entry(&Keys::Id.to_string())
@@ -291,6 +315,26 @@ impl DesktopFile {
         );
     }
 
+    pub fn get_comment(&self) -> Option<String> {
+        self.desktop_entry
+            .desktop_entry(&Keys::Comment.to_string())
+            .and_then(map_to_string_option)
+    }
+
+    pub fn set_comment(&mut self, comment: &str) {
+        self.desktop_entry
+            .add_desktop_entry(Keys::Comment.to_string(), comment.to_string());
+
+        debug!(
+            "Set '{}' on desktop file: {}",
+            &Keys::Comment.to_string(),
+            &self
+                .desktop_entry
+                .desktop_entry(&Keys::Comment.to_string())
+                .unwrap_or_default()
+        );
+    }
+
     pub fn get_url(&self) -> Option<String> {
         self.desktop_entry
             .desktop_entry(&Keys::Url.to_string())
@@ -318,6 +362,13 @@ impl DesktopFile {
             .and_then(|browser_id| self.browser_configs.get_by_id(&browser_id))
     }
 
+    /// Also set when the browser is not installed or not detected yet
+    pub fn get_browser_id(&self) -> Option<String> {
+        self.desktop_entry
+            .desktop_entry(&Keys::BrowserId.to_string())
+            .and_then(map_to_string_option)
+    }
+
     pub fn set_browser(&mut self, browser: &Rc<Browser>) {
         self.desktop_entry
             .add_desktop_entry(Keys::BrowserId.to_string(), browser.id.clone());
@@ -371,21 +422,6 @@ impl DesktopFile {
         );
     }
 
-    pub fn get_icon(&self) -> Image {
-        let fallback_icon = "image-missing-symbolic";
-        let icon_name = self.desktop_entry.icon().unwrap_or_default();
-        let icon_path = Path::new(icon_name);
-        if icon_path.is_file() {
-            Image::from_file(icon_path)
-        } else if !icon_name.is_empty() {
-            Image::from_icon_name(icon_name)
-        } else {
-            let image = Image::from_icon_name(fallback_icon);
-            image.add_css_class("error");
-            image
-        }
-    }
-
     pub fn get_icon_path(&self) -> Option<PathBuf> {
         self.desktop_entry
             .desktop_entry(&Keys::Icon.to_string())
@@ -428,6 +464,81 @@ impl DesktopFile {
         );
     }
 
+    pub fn get_actions(&self) -> Vec<DesktopAction> {
+        let Some(action_ids) = self.desktop_entry.actions() else {
+            return Vec::new();
+        };
+
+        action_ids
+            .into_iter()
+            .filter(|action_id| !action_id.is_empty())
+            .filter_map(|action_id| {
+                let name = self
+                    .desktop_entry
+                    .action_entry(action_id, &Keys::Name.to_string())?;
+                let url = self
+                    .desktop_entry
+                    .action_entry(action_id, &Keys::Url.to_string())?;
+
+                Some(DesktopAction {
+                    name: name.to_string(),
+                    url: url.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces all actions, `Exec` is generated on save
+    pub fn set_actions(&mut self, actions: &[DesktopAction]) {
+        let groups = &mut self.desktop_entry.groups.0;
+        groups.retain(|group_name, _| !group_name.starts_with(Self::ACTION_GROUP_PREFIX));
+
+        let mut action_ids = Vec::new();
+        for (index, action) in actions.iter().enumerate() {
+            let action_id = Self::get_action_id(index);
+            let mut group = Group::default();
+            group.0.insert(
+                Keys::Name.to_string(),
+                (action.name.clone(), LocaleMap::default()),
+            );
+            group.0.insert(
+                Keys::Url.to_string(),
+                (action.url.clone(), LocaleMap::default()),
+            );
+            groups.insert(format!("{}{action_id}", Self::ACTION_GROUP_PREFIX), group);
+            action_ids.push(action_id);
+        }
+
+        if action_ids.is_empty() {
+            if let Some(group) = groups.get_mut("Desktop Entry") {
+                group.0.remove(&Keys::Actions.to_string());
+            }
+        } else {
+            self.desktop_entry
+                .add_desktop_entry(Keys::Actions.to_string(), action_ids.join(";") + ";");
+        }
+
+        debug!(
+            "Set '{}' on desktop file: {:?}",
+            &Keys::Actions.to_string(),
+            actions
+        );
+    }
+
+    fn get_action_id(index: usize) -> String {
+        format!("action-{}", index + 1)
+    }
+
+    fn set_action_exec(&mut self, action_id: &str, exec: &str) {
+        let group_name = format!("{}{action_id}", Self::ACTION_GROUP_PREFIX);
+        if let Some(group) = self.desktop_entry.groups.0.get_mut(&group_name) {
+            group.0.insert(
+                Keys::Exec.to_string(),
+                (exec.to_string(), LocaleMap::default()),
+            );
+        }
+    }
+
     pub fn copy_profile_config_to_profile_path(&self, profile_path: &Path) -> Result<()> {
         let browser = self.get_browser().context("No browser on 'DesktopFile'")?;
 
@@ -460,26 +571,130 @@ impl DesktopFile {
             Ok(())
         };
 
-        let config_path = self
-            .app_dirs
-            .config()
-            .join("profiles")
-            .join(&browser.config_name);
-        if config_path.is_dir() {
-            return copy_profile_config(&config_path);
+        // Lower layers are copied first so higher layers overwrite their files
+        let profile_config_paths = |name: &str| -> Vec<PathBuf> {
+            self.app_dirs
+                .config_layers()
+                .iter()
+                .map(|layer| layer.join("profiles").join(name))
+                .filter(|path| path.is_dir())
+                .collect()
+        };
+
+        let mut config_paths = profile_config_paths(&browser.config_name);
+        if config_paths.is_empty() {
+            config_paths = match browser.base {
+                Base::Chromium => profile_config_paths("chromium"),
+                Base::Firefox => profile_config_paths("firefox"),
+                Base::None => Vec::new(),
+            };
         }
 
-        match browser.base {
-            Base::Chromium => {
-                let config_path = self.app_dirs.config().join("profiles").join("chromium");
-                copy_profile_config(&config_path)
-            }
-            Base::Firefox => {
-                let config_path = self.app_dirs.config().join("profiles").join("firefox");
-                copy_profile_config(&config_path)
-            }
-            Base::None => Ok(()),
+        for config_path in &config_paths {
+            copy_profile_config(config_path)?;
         }
+
+        Ok(())
+    }
+
+    /// Dirs a profile is allowed to be removed from.
+    /// The browser can have changed since the profile was created, so all browsers are included.
+    pub fn get_profile_roots(&self) -> Vec<PathBuf> {
+        let mut roots = vec![self.app_dirs.profiles()];
+        roots.extend(
+            self.browser_configs
+                .get_all_browsers()
+                .iter()
+                .chain(self.browser_configs.get_uninstalled_browsers().iter())
+                .flat_map(|browser| {
+                    [
+                        browser.get_flatpak_profile_root(),
+                        browser.get_snap_profile_root(),
+                    ]
+                })
+                .flatten(),
+        );
+        roots
+    }
+
+    pub fn remove_profile(&self, profile_path: &Path) -> Result<()> {
+        utils::files::remove_dir_all_contained(profile_path, &self.get_profile_roots())
+    }
+
+    /// Themed icon name, also the file name of the installed icons
+    pub fn get_icon_name(&self) -> Option<String> {
+        self.get_id()
+            .map(|id| format!("{}.{id}", config::APP_ID.get_value()))
+    }
+
+    /// Install the icon in the user's hicolor theme and point `Icon` to its name
+    pub fn install_icon(&mut self, pixbuf: &Pixbuf, svg: Option<&[u8]>) -> Result<()> {
+        let icon_name = self.get_icon_name().context("No id on 'DesktopFile'")?;
+        let hicolor = self.app_dirs.hicolor();
+
+        self.remove_icon().context("Failed to remove old icon")?;
+        icon_theme::install(&hicolor, &icon_name, pixbuf, svg)?;
+        icon_theme::refresh_cache(&hicolor);
+
+        self.set_icon_path(Path::new(&icon_name));
+        Ok(())
+    }
+
+    /// Point `Icon` to an icon of the user's theme instead, so it follows the theme
+    pub fn set_themed_icon(&mut self, icon_name: &str) -> Result<()> {
+        self.remove_icon().context("Failed to remove old icon")?;
+        icon_theme::refresh_cache(&self.app_dirs.hicolor());
+
+        self.set_icon_path(Path::new(icon_name));
+        Ok(())
+    }
+
+    /// Same as `install_icon` for a png or svg file
+    pub fn install_icon_from_file(&mut self, path: &Path) -> Result<()> {
+        let pixbuf =
+            Pixbuf::from_file(path).context(format!("Failed to load icon: {}", path.display()))?;
+        let svg = if path.extension().is_some_and(|extension| extension == "svg") {
+            Some(fs::read(path).context(format!("Failed to read icon: {}", path.display()))?)
+        } else {
+            None
+        };
+
+        self.install_icon(&pixbuf, svg.as_deref())
+    }
+
+    /// Same as `install_icon` for an svg in memory, like a monogram
+    pub fn install_icon_from_svg(&mut self, svg: &[u8]) -> Result<()> {
+        let loader = PixbufLoader::new();
+        loader.write(svg)?;
+        loader.close()?;
+        let pixbuf = loader.pixbuf().context("No image in svg")?;
+
+        self.install_icon(&pixbuf, Some(svg))
+    }
+
+    /// Installed icon file to show in the UI
+    pub fn get_icon_file(&self) -> Option<PathBuf> {
+        let icon_path = self.get_icon_path()?;
+        if icon_path.is_absolute() {
+            return Some(icon_path).filter(|icon_path| icon_path.is_file());
+        }
+        icon_theme::find(&self.app_dirs.hicolor(), &self.get_icon_name()?)
+    }
+
+    /// Removes all installed sizes, and the single icon file older versions saved
+    pub fn remove_icon(&self) -> Result<()> {
+        if let Some(icon_path) = self.get_icon_path()
+            && icon_path.is_absolute()
+            && icon_path.is_file()
+        {
+            utils::files::remove_file_contained(&icon_path, &[self.app_dirs.icons()])?;
+        }
+
+        if let Some(icon_name) = self.get_icon_name() {
+            icon_theme::uninstall(&self.app_dirs.hicolor(), &icon_name)?;
+        }
+
+        Ok(())
     }
 
     pub fn build_profile_path(&self) -> Result<PathBuf> {
@@ -530,6 +745,10 @@ impl DesktopFile {
         let new_desktop_file = self.to_new_from_browser()?;
 
         if self.desktop_entry.path.is_file() && !self.desktop_entry.path.is_symlink() {
+            utils::files::ensure_contained(
+                &self.desktop_entry.path,
+                &[self.app_dirs.applications()],
+            )?;
             match fs::remove_file(&self.desktop_entry.path) {
                 Ok(()) => {}
                 Err(error) => {
@@ -541,52 +760,54 @@ impl DesktopFile {
         let save_path = new_desktop_file.desktop_entry.path.clone();
 
         debug!("Saving desktop file to: {}", save_path.display());
-        fs::write(&save_path, new_desktop_file.desktop_entry.to_string())
-            .context("Saving desktop file")?;
+        fs::write(
+            &save_path,
+            escape::to_string(&new_desktop_file.desktop_entry),
+        )
+        .context("Saving desktop file")?;
         self.desktop_entry = new_desktop_file.desktop_entry;
 
         Ok(())
     }
 
     pub fn delete(&self) -> Result<()> {
-        let mut is_error = false;
+        let mut errors = Vec::new();
 
         if self.desktop_entry.path.is_file() {
-            match fs::remove_file(&self.desktop_entry.path) {
+            match utils::files::remove_file_contained(
+                &self.desktop_entry.path,
+                &[self.app_dirs.applications()],
+            ) {
                 Ok(()) => {}
                 Err(error) => {
                     error!("Failed to remove desktop file: {error:?}");
-                    is_error = true;
+                    errors.push(error.to_string());
                 }
             }
         }
 
-        if let Some(icon_path) = self.get_icon_path()
-            && icon_path.is_file()
-        {
-            match fs::remove_file(icon_path) {
-                Ok(()) => {}
-                Err(error) => {
-                    error!("Failed to remove icon file: {error:?}");
-                    is_error = true;
-                }
+        match self.remove_icon() {
+            Ok(()) => icon_theme::refresh_cache(&self.app_dirs.hicolor()),
+            Err(error) => {
+                error!("Failed to remove icon file: {error:?}");
+                errors.push(error.to_string());
             }
         }
 
         if let Some(profile_path) = self.get_profile_path()
             && Path::new(&profile_path).is_dir()
         {
-            match fs::remove_dir_all(profile_path) {
+            match self.remove_profile(&profile_path) {
                 Ok(()) => {}
                 Err(error) => {
                     error!("Failed to remove profile: {error:?}");
-                    is_error = true;
+                    errors.push(error.to_string());
                 }
             }
         }
 
-        if is_error {
-            bail!("Some files could not be removed, check logs")
+        if !errors.is_empty() {
+            bail!("Some files could not be removed: {}", errors.join("\n"))
         }
 
         info!(
@@ -639,6 +860,66 @@ impl DesktopFile {
         Ok(true)
     }
 
+    /// Apps of a system browser that is now found as snap, like on Ubuntu where `firefox`
+    /// starts the snap. The profile is moved to where the snap can write to.
+    pub fn migrate_to_snap(&mut self) -> Result<bool, DesktopFileError> {
+        let Some(browser_id) = self.get_browser_id() else {
+            return Ok(false);
+        };
+        if self.get_browser().is_some() {
+            return Ok(false);
+        }
+        let Some(snap_browser) = self.browser_configs.get_snap_by_system_bin(&browser_id) else {
+            return Ok(false);
+        };
+        info!(
+            "Moving web app from system browser '{browser_id}' to snap '{}'",
+            snap_browser.id
+        );
+
+        let is_isolated = self.get_isolated().unwrap_or(false);
+        if is_isolated
+            && snap_browser.can_isolate
+            && let Some(old_profile_path) = self.get_profile_path().filter(|path| path.is_dir())
+        {
+            let id = self.get_id().context("No id on 'DesktopFile'")?;
+            let new_profile_path = snap_browser.get_profile_path()?.join(&id);
+            utils::files::ensure_contained(&old_profile_path, &self.get_profile_roots())?;
+
+            // Nothing is changed, so the old profile is not left behind outside the profile roots
+            if new_profile_path.exists() {
+                return Err(anyhow!(
+                    "Profile already exists, move or remove it first: {}",
+                    new_profile_path.display()
+                )
+                .into());
+            }
+
+            info!(
+                from = old_profile_path.display().to_string(),
+                to = new_profile_path.display().to_string(),
+                "Moving profile"
+            );
+            if let Some(parent) = new_profile_path.parent() {
+                fs::create_dir_all(parent)
+                    .context(format!("Failed to create: {}", parent.display()))?;
+            }
+            fs::rename(&old_profile_path, &new_profile_path).context(format!(
+                "Failed to move profile to: {}",
+                new_profile_path.display()
+            ))?;
+        }
+
+        self.set_browser(&snap_browser);
+        if is_isolated && snap_browser.can_isolate {
+            let profile_path = self.build_profile_path()?;
+            self.set_profile_path(&profile_path);
+        }
+        self.save()?;
+
+        Ok(true)
+    }
+
     /// Check paths, try to fix and print errors
     pub fn check_paths(&self) {
         let entries = match self.get_entries() {
@@ -666,11 +947,48 @@ impl DesktopFile {
             let _ = self.build_profile_path();
         }
 
-        if !entries.icon_path.is_file() {
+        // Names are looked up in the icon theme
+        if entries.icon_path.is_absolute() && !entries.icon_path.is_file() {
             error!(name = entries.name, "Icon file does not exists");
         }
     }
 
+    /// The desktop file the browser would generate for an example app
+    pub fn preview_browser(
+        browser: &Rc<Browser>,
+        browser_configs: &Rc<BrowserConfigs>,
+        app_dirs: &Rc<AppDirs>,
+    ) -> Result<String, DesktopFileError> {
+        let app_id = "a1b2c3d4".to_string();
+        let url = Url::parse("https://example.com/app").context("Invalid example url")?;
+        let profile_path = browser.get_profile_path().ok();
+        let icon_name = format!("{}.{app_id}", config::APP_ID.get_value());
+
+        let entries = DesktopFileEntries {
+            name: "Example".to_string(),
+            version: Version::parse(config::VERSION.get_value())
+                .context("Failed to get app version")?,
+            browser: browser.clone(),
+            url: url.to_string(),
+            url_path: url.path().to_string(),
+            host: url.host_str().unwrap_or_default().to_string(),
+            domain: url.get_domain().unwrap_or_default(),
+            isolate: profile_path.is_some(),
+            maximize: browser.can_start_maximized,
+            icon_path: PathBuf::from(icon_name),
+            profile_path: profile_path
+                .map(|path| path.join(&app_id))
+                .unwrap_or_default(),
+            actions: Vec::new(),
+            app_id,
+        };
+        let save_path = Self::build_save_path(app_dirs, browser, &entries.app_id);
+        let desktop_file =
+            Self::from_entries(&entries, None, &save_path, browser_configs, app_dirs)?;
+
+        Ok(desktop_file.to_string())
+    }
+
     fn get_entries(&self) -> Result<DesktopFileEntries, DesktopFileError> {
         let name = self.get_name().ok_or(ValidationError {
             field: Keys::Name,
@@ -684,40 +1002,34 @@ impl DesktopFile {
             field: Keys::Version,
             message: "Missing".to_string(),
         })?;
-        let url = self
-            .get_url()
-            .ok_or(ValidationError {
-                field: Keys::Url,
-                message: "Missing".to_string(),
-            })
-            .ok()
-            .filter(|url| Url::parse(url).is_ok())
-            .ok_or(ValidationError {
-                field: Keys::Url,
-                message: "Invalid".to_string(),
-            })?;
+        let url = self.get_url().ok_or(ValidationError {
+            field: Keys::Url,
+            message: "Missing".to_string(),
+        })?;
+        let parsed_url =
+            Url::parse(&url)
+                .ok()
+                .filter(UrlExt::is_web_url)
+                .ok_or(ValidationError {
+                    field: Keys::Url,
+                    message: "Invalid".to_string(),
+                })?;
         let browser = self.get_browser().ok_or(ValidationError {
             field: Keys::BrowserId,
             message: "Missing".to_string(),
         })?;
-        let domain = Url::parse(&url)
-            .map_err(|_| ValidationError {
-                field: Keys::Url,
-                message: "Invalid url".to_string(),
-            })?
-            .domain()
+        let host = parsed_url
+            .host_str()
             .and_then(map_to_string_option)
             .ok_or(ValidationError {
                 field: Keys::Url,
-                message: "Invalid domain".to_string(),
+                message: "Invalid host".to_string(),
             })?;
-        let url_path = Url::parse(&url)
-            .map_err(|_| ValidationError {
-                field: Keys::Url,
-                message: "Invalid url".to_string(),
-            })?
-            .path()
-            .to_string();
+        let domain = parsed_url.get_domain().ok_or(ValidationError {
+            field: Keys::Url,
+            message: "Invalid domain".to_string(),
+        })?;
+        let url_path = parsed_url.path().to_string();
         let isolate = self.get_isolated().ok_or(ValidationError {
             field: Keys::Isolate,
             message: "Missing".to_string(),
@@ -744,6 +1056,18 @@ impl DesktopFile {
                 message: "Missing".to_string(),
             })?;
 
+        let actions = self.get_actions();
+        for action in &actions {
+            if action.name.is_empty() || !Url::parse(&action.url).is_ok_and(|url| url.is_web_url())
+            {
+                return Err(ValidationError {
+                    field: Keys::Actions,
+                    message: format!("Invalid quick link '{}'", action.name),
+                }
+                .into());
+            }
+        }
+
         Ok(DesktopFileEntries {
             name,
             app_id,
@@ -751,28 +1075,33 @@ impl DesktopFile {
             browser,
             url,
             url_path,
+            host,
             domain,
             isolate,
             maximize,
             icon_path: icon,
             profile_path,
+            actions,
         })
     }
 
     fn get_save_path(&self) -> Result<PathBuf> {
-        let applications_dir = self.app_dirs.applications();
+        let browser = self.get_browser().context("Failed to get browser")?;
+        let id = self.get_id().context("Failed to get my id")?;
+
+        Ok(Self::build_save_path(&self.app_dirs, &browser, &id))
+    }
+
+    fn build_save_path(app_dirs: &AppDirs, browser: &Browser, id: &str) -> PathBuf {
         let file_name = format!(
-            "{}-{}-{}",
-            self.get_browser()
-                .context("Failed to get browser")?
-                .desktop_file_name_prefix,
+            "{}-{}-{id}",
+            browser.desktop_file_name_prefix,
             config::APP_NAME_SHORT.get_value(),
-            self.get_id().context("Failed to get my id")?
         );
-        let mut desktop_file_path = applications_dir.join(file_name);
+        let mut desktop_file_path = app_dirs.applications().join(file_name);
         desktop_file_path.add_extension("desktop");
 
-        Ok(desktop_file_path)
+        desktop_file_path
     }
 
     fn replace_conditional(
@@ -790,6 +1119,7 @@ impl DesktopFile {
             let re = Regex::new(&format!(r"%\{{{conditional_key}\s*\?\s*[^}}]+\}}",)).unwrap();
 
             let replacement = if set_value && let Some(with_value) = with_value {
+                let with_value = escape::escape_string(&escape::escape_exec_arg(with_value, false));
                 format!("{replace_value}={with_value}")
             } else if set_value {
                 replace_value
@@ -801,35 +1131,45 @@ impl DesktopFile {
         }
     }
 
-    fn to_new_from_browser(&self) -> Result<DesktopFile, DesktopFileError> {
-        let entries = &self.get_entries()?;
-        let save_path = self.get_save_path()?;
+    /// Fill the browser's desktop file template, `url` is what the app opens
+    fn fill_browser_template(
+        entries: &DesktopFileEntries,
+        url: &str,
+    ) -> Result<String, DesktopFileError> {
         let app_name_short = config::APP_NAME_SHORT.get_value();
         let app_id = format!("{}-{}", app_name_short, entries.app_id);
 
-        let domain_path = match self.get_browser() {
-            None => &entries.domain,
-            Some(browser) => &match browser.base {
-                Base::Chromium => {
-                    let domain = format!("{}/", entries.domain);
-                    let domain_path = format!("{domain}{}", entries.url_path);
-                    domain_path.replace('/', "_")
-                }
-                // Not needed for other browser atm
-                _ => {
-                    format!("{}{}", entries.domain, entries.url_path)
-                }
-            },
+        let domain_path = &match entries.browser.base {
+            // Same as the app name Chromium generates for `--app`: `<host>_<path>`.
+            // It leaves out the port and replaces characters not allowed in file names.
+            Base::Chromium => {
+                let domain_path = format!("{}_{}", entries.host, entries.url_path);
+                domain_path.replace(['/', ':'], "_")
+            }
+            // Not needed for other browser atm
+            _ => {
+                format!("{}{}", entries.domain, entries.url_path)
+            }
         };
 
-        let mut d_str = entries.browser.desktop_file.clone().to_string();
-        d_str = d_str.replace("%{command}", &entries.browser.get_command()?);
-        d_str = d_str.replace("%{name}", &entries.name);
-        d_str = d_str.replace("%{url}", &entries.url);
-        d_str = d_str.replace("%{domain}", &entries.domain);
-        d_str = d_str.replace("%{domain_path}", domain_path);
-        d_str = d_str.replace("%{icon}", &entries.icon_path.to_string_lossy());
-        d_str = d_str.replace("%{app_id}", &app_id);
+        let mut d_str = escape::to_string(&entries.browser.desktop_file);
+        // The command can hold multiple arguments so it is not escaped, a path is one argument
+        let mut command = entries.browser.get_command()?;
+        if entries.browser.is_path() {
+            command = escape::escape_string(&escape::escape_exec_arg(&command, false));
+        }
+        d_str = d_str.replace("%{command}", &command);
+        d_str = escape::fill_template(
+            &d_str,
+            &[
+                ("name", &entries.name),
+                ("url", url),
+                ("domain", &entries.domain),
+                ("domain_path", domain_path),
+                ("icon", &entries.icon_path.to_string_lossy()),
+                ("app_id", &app_id),
+            ],
+        );
         Self::replace_conditional(
             "is_isolated",
             entries.isolate,
@@ -838,8 +1178,32 @@ impl DesktopFile {
         );
         Self::replace_conditional("is_maximized", entries.maximize, None, &mut d_str);
 
-        let mut new_desktop_file =
-            Self::from_string(&save_path, &d_str, &self.browser_configs, &self.app_dirs)?;
+        Ok(d_str)
+    }
+
+    fn to_new_from_browser(&self) -> Result<DesktopFile, DesktopFileError> {
+        let entries = &self.get_entries()?;
+        let save_path = self.get_save_path()?;
+
+        Self::from_entries(
+            entries,
+            self.get_comment().as_deref(),
+            &save_path,
+            &self.browser_configs,
+            &self.app_dirs,
+        )
+    }
+
+    fn from_entries(
+        entries: &DesktopFileEntries,
+        comment: Option<&str>,
+        save_path: &Path,
+        browser_configs: &Rc<BrowserConfigs>,
+        app_dirs: &Rc<AppDirs>,
+    ) -> Result<DesktopFile, DesktopFileError> {
+        let d_str = Self::fill_browser_template(entries, &entries.url)?;
+
+        let mut new_desktop_file = Self::from_string(save_path, &d_str, browser_configs, app_dirs)?;
 
         new_desktop_file.set_is_owned_app();
         new_desktop_file.set_id(&entries.app_id);
@@ -849,12 +1213,89 @@ impl DesktopFile {
         new_desktop_file.set_isolated(entries.isolate);
         new_desktop_file.set_maximized(entries.maximize);
         new_desktop_file.set_profile_path(&entries.profile_path);
+        if let Some(comment) = comment {
+            new_desktop_file.set_comment(comment);
+        }
+
+        // Actions run the same command as the app, only with their own url
+        new_desktop_file.set_actions(&entries.actions);
+        for (index, action) in entries.actions.iter().enumerate() {
+            let action_d_str = Self::fill_browser_template(entries, &action.url)?;
+            let action_entry = DesktopEntry::from_str(save_path, &action_d_str, None::<&[String]>)
+                .context("Failed to parse desktop file for action")?;
+            let exec = action_entry
+                .exec()
+                .context("Browser desktop file has no Exec")?;
+            new_desktop_file.set_action_exec(&Self::get_action_id(index), exec);
+        }
+
+        Self::validate_round_trip(&new_desktop_file, entries)?;
 
         Ok(new_desktop_file)
     }
+
+    /// Parse the generated file again to make sure no value escaped its key
+    fn validate_round_trip(
+        desktop_file: &DesktopFile,
+        entries: &DesktopFileEntries,
+    ) -> Result<(), DesktopFileError> {
+        let d_str = escape::to_string(&desktop_file.desktop_entry);
+        let parsed =
+            DesktopEntry::from_str(&desktop_file.desktop_entry.path, &d_str, None::<&[String]>)
+                .context("Failed to parse generated desktop file")?;
+
+        if parsed.desktop_entry(&Keys::Name.to_string()) != Some(entries.name.as_str()) {
+            error!(
+                name = entries.name,
+                "Name does not round-trip in desktop file"
+            );
+            return Err(ValidationError {
+                field: Keys::Name,
+                message: "Contains characters that can't be saved".to_string(),
+            }
+            .into());
+        }
+
+        if parsed.desktop_entry(&Keys::Url.to_string()) != Some(entries.url.as_str())
+            || !Self::is_url_in_exec(parsed.exec(), &entries.url)
+        {
+            error!(url = entries.url, "Url does not round-trip in desktop file");
+            return Err(ValidationError {
+                field: Keys::Url,
+                message: "Contains characters that can't be saved".to_string(),
+            }
+            .into());
+        }
+
+        for (index, action) in entries.actions.iter().enumerate() {
+            let action_id = Self::get_action_id(index);
+            if parsed.action_entry(&action_id, &Keys::Name.to_string()) != Some(&action.name)
+                || !Self::is_url_in_exec(parsed.action_exec(&action_id), &action.url)
+            {
+                error!(
+                    name = action.name,
+                    url = action.url,
+                    "Action does not round-trip in desktop file"
+                );
+                return Err(ValidationError {
+                    field: Keys::Actions,
+                    message: "Contains characters that can't be saved".to_string(),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_url_in_exec(exec: Option<&str>, url: &str) -> bool {
+        let url_arg = format!("={url}");
+        exec.and_then(|exec| escape::parse_exec(exec).ok())
+            .is_some_and(|args| args.iter().any(|arg| arg == url || arg.ends_with(&url_arg)))
+    }
 }
 impl std::fmt::Display for DesktopFile {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        self.desktop_entry.fmt(f)
+        f.write_str(&escape::to_string(&self.desktop_entry))
     }
 }
@@ -0,0 +1,141 @@
+// Escaping rules from the Desktop Entry spec:
+// https://specifications.freedesktop.org/desktop-entry-spec/latest/value-types.html
+// https://specifications.freedesktop.org/desktop-entry-spec/latest/exec-variables.html
+
+use anyhow::{Context, Result};
+use freedesktop_desktop_entry::DesktopEntry;
+use std::fmt::Write as _;
+
+const EXEC_RESERVED_CHARS: &[char] = &[
+    ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')',
+    '`',
+];
+const EXEC_QUOTED_ESCAPE_CHARS: &[char] = &['"', '`', '$', '\\'];
+
+/// Escape a value so it can be written after `Key=`.
+pub fn escape_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for (index, char) in value.chars().enumerate() {
+        match char {
+            '\\' => escaped.push_str(r"\\"),
+            '\n' => escaped.push_str(r"\n"),
+            '\t' => escaped.push_str(r"\t"),
+            '\r' => escaped.push_str(r"\r"),
+            // A leading space is dropped by parsers
+            ' ' if index == 0 => escaped.push_str(r"\s"),
+            _ => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+/// Escape a value as (part of) an `Exec` argument.
+/// Values outside quotes are quoted when they contain reserved characters.
+/// The result still needs `escape_string` before writing it to a file.
+pub fn escape_exec_arg(value: &str, is_quoted: bool) -> String {
+    let needs_quotes = !is_quoted && value.contains(EXEC_RESERVED_CHARS);
+    let mut escaped = String::with_capacity(value.len() + 2);
+
+    if needs_quotes {
+        escaped.push('"');
+    }
+    for char in value.chars() {
+        if char == '%' {
+            escaped.push_str("%%");
+        } else if (is_quoted || needs_quotes) && EXEC_QUOTED_ESCAPE_CHARS.contains(&char) {
+            escaped.push('\\');
+            escaped.push(char);
+        } else {
+            escaped.push(char);
+        }
+    }
+    if needs_quotes {
+        escaped.push('"');
+    }
+
+    escaped
+}
+
+/// Split an `Exec` value into the arguments a launcher would run.
+/// Web apps don't use field codes, so only `%%` needs to be expanded.
+pub fn parse_exec(exec: &str) -> Result<Vec<String>> {
+    let exec = exec.replace("%%", "%");
+    let args = glib::shell_parse_argv(&exec).context(format!("Invalid Exec: '{exec}'"))?;
+
+    Ok(args
+        .into_iter()
+        .map(|arg| arg.to_string_lossy().to_string())
+        .collect())
+}
+
+/// Replace `%{key}` placeholders in a desktop file template.
+/// Values in `Exec` are quoted as arguments, all values are escaped as strings.
+pub fn fill_template(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+
+    for line in template.lines() {
+        let is_exec = line
+            .split_once('=')
+            .is_some_and(|(key, _)| key.trim() == "Exec");
+
+        let mut rest = line;
+        let mut is_quoted = false;
+        let mut is_escaped = false;
+
+        while !rest.is_empty() {
+            if let Some((key, value)) = values
+                .iter()
+                .find(|(key, _)| rest.starts_with(&format!("%{{{key}}}")))
+            {
+                let value = if is_exec {
+                    escape_exec_arg(value, is_quoted)
+                } else {
+                    (*value).to_string()
+                };
+                filled.push_str(&escape_string(&value));
+                rest = &rest[key.len() + 3..];
+                continue;
+            }
+
+            let mut chars = rest.chars();
+            let char = chars.next().unwrap_or_default();
+            if is_exec && char == '"' && !is_escaped {
+                is_quoted = !is_quoted;
+            }
+            is_escaped = char == '\\' && !is_escaped;
+            filled.push(char);
+            rest = chars.as_str();
+        }
+
+        filled.push('\n');
+    }
+
+    filled
+}
+
+/// Same as the `Display` of `DesktopEntry`, but with escaped values
+/// and the `[Desktop Entry]` group first as the spec requires.
+pub fn to_string(desktop_entry: &DesktopEntry) -> String {
+    const MAIN_GROUP: &str = "Desktop Entry";
+    let mut string = String::new();
+
+    let groups = &desktop_entry.groups.0;
+    let main_group = groups.iter().filter(|(name, _)| *name == MAIN_GROUP);
+    let other_groups = groups.iter().filter(|(name, _)| *name != MAIN_GROUP);
+
+    for (group_name, group) in main_group.chain(other_groups) {
+        let _ = writeln!(string, "[{group_name}]");
+
+        for (key, (value, localizations)) in &group.0 {
+            let _ = writeln!(string, "{key}={}", escape_string(value));
+            for (locale, localized) in localizations {
+                let _ = writeln!(string, "{key}[{locale}]={}", escape_string(localized));
+            }
+        }
+        string.push('\n');
+    }
+
+    string
+}
@@ -0,0 +1,149 @@
+// Icon theme layout: https://specifications.freedesktop.org/icon-theme-spec/latest/
+
+use crate::utils::{self, command::HostCommand};
+use anyhow::{Context, Result};
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+use tracing::{debug, error};
+
+const SIZES: [i32; 8] = [16, 24, 32, 48, 64, 128, 256, 512];
+const SCALABLE: &str = "scalable";
+const CONTEXT: &str = "apps";
+const CACHE_COMMAND: &str = "gtk-update-icon-cache";
+
+/// Write the icon at the standard sizes up to its own size, so small icons are not blown up.
+/// Plus the svg as `scalable` when there is one.
+pub fn install(hicolor: &Path, icon_name: &str, pixbuf: &Pixbuf, svg: Option<&[u8]>) -> Result<()> {
+    let source_size = pixbuf.width().max(pixbuf.height());
+    // An icon smaller than all sizes still needs one
+    let sizes = SIZES
+        .iter()
+        .copied()
+        .filter(|size| *size <= source_size.max(SIZES[0]));
+
+    for size in sizes {
+        let dir = hicolor.join(format!("{size}x{size}")).join(CONTEXT);
+        fs::create_dir_all(&dir).context(format!("Could not create: {}", dir.display()))?;
+
+        let path = dir.join(format!("{icon_name}.png"));
+        debug!("Saving icon to fs: {}", path.display());
+
+        to_square(pixbuf, size)?
+            .savev(&path, "png", &[])
+            .context(format!("Failed to save icon: {}", path.display()))?;
+    }
+
+    if let Some(svg) = svg {
+        let dir = hicolor.join(SCALABLE).join(CONTEXT);
+        fs::create_dir_all(&dir).context(format!("Could not create: {}", dir.display()))?;
+
+        let path = dir.join(format!("{icon_name}.svg"));
+        debug!("Saving icon to fs: {}", path.display());
+
+        fs::write(&path, svg).context(format!("Failed to save icon: {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Remove the icon from every size folder, not only the ones `install` writes
+pub fn uninstall(hicolor: &Path, icon_name: &str) -> Result<()> {
+    for path in find_all(hicolor, icon_name) {
+        debug!("Removing icon: {}", path.display());
+        utils::files::remove_file_contained(&path, &[hicolor.to_path_buf()])?;
+    }
+
+    Ok(())
+}
+
+/// The largest installed bitmap, or the scalable icon
+pub fn find(hicolor: &Path, icon_name: &str) -> Option<PathBuf> {
+    SIZES
+        .iter()
+        .rev()
+        .map(|size| {
+            hicolor
+                .join(format!("{size}x{size}"))
+                .join(CONTEXT)
+                .join(format!("{icon_name}.png"))
+        })
+        .chain([hicolor
+            .join(SCALABLE)
+            .join(CONTEXT)
+            .join(format!("{icon_name}.svg"))])
+        .find(|path| path.is_file())
+}
+
+/// Let desktops pick up added or removed icons, nothing to do without the cache command
+pub fn refresh_cache(hicolor: &Path) {
+    static HAS_CACHE_COMMAND: OnceLock<bool> = OnceLock::new();
+    let has_cache_command = *HAS_CACHE_COMMAND
+        .get_or_init(|| utils::command::test_command_available_sync(CACHE_COMMAND));
+    if !has_cache_command {
+        debug!("No '{CACHE_COMMAND}', not refreshing the icon cache");
+        return;
+    }
+
+    let result = HostCommand::new(CACHE_COMMAND)
+        .args(&["--force", "--ignore-theme-index", "--quiet"])
+        .arg(&hicolor.to_string_lossy())
+        .run_background();
+
+    if let Err(error) = result {
+        error!(
+            error = error.to_string(),
+            "Failed to refresh the icon cache"
+        );
+    }
+}
+
+fn find_all(hicolor: &Path, icon_name: &str) -> Vec<PathBuf> {
+    let Ok(size_dirs) = fs::read_dir(hicolor) else {
+        return Vec::new();
+    };
+
+    size_dirs
+        .filter_map(Result::ok)
+        .flat_map(|size_dir| {
+            let dir = size_dir.path().join(CONTEXT);
+            ["png", "svg"].map(|extension| dir.join(format!("{icon_name}.{extension}")))
+        })
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+/// Scale to fit and center on a transparent square
+fn to_square(pixbuf: &Pixbuf, size: i32) -> Result<Pixbuf> {
+    let width = pixbuf.width();
+    let height = pixbuf.height();
+    let scale = f64::from(size) / f64::from(width.max(height));
+    #[allow(clippy::cast_possible_truncation)]
+    let scaled_width = ((f64::from(width) * scale).round() as i32).clamp(1, size);
+    #[allow(clippy::cast_possible_truncation)]
+    let scaled_height = ((f64::from(height) * scale).round() as i32).clamp(1, size);
+
+    let scaled = pixbuf
+        .scale_simple(scaled_width, scaled_height, InterpType::Hyper)
+        .context("Failed to scale icon")?
+        .add_alpha(false, 0, 0, 0)
+        .context("Failed to add alpha to icon")?;
+
+    let square =
+        Pixbuf::new(Colorspace::Rgb, true, 8, size, size).context("Failed to create icon")?;
+    square.fill(0);
+    scaled.copy_area(
+        0,
+        0,
+        scaled_width,
+        scaled_height,
+        &square,
+        (size - scaled_width) / 2,
+        (size - scaled_height) / 2,
+    );
+
+    Ok(square)
+}
@@ -1,69 +1,306 @@
+mod cache;
+mod config;
+mod tls;
+
+use crate::app_dirs::AppDirs;
 use anyhow::{Result, bail};
-use gtk::gio::{self};
-use std::time::Duration;
-use tracing::{debug, error};
-use ureq::Agent;
+use cache::{CacheControl, CacheEntry, FetchCache};
+use config::FetchConfig;
+use gio::{Cancellable, prelude::CancellableExt};
+use std::{cell::OnceCell, io, rc::Rc, sync::Arc, thread};
+use tls::CertificateTrust;
+use tracing::{debug, error, info};
+use ureq::{
+    Agent, Body, ResponseExt,
+    http::{Response, StatusCode, header},
+};
+use url::Url;
 
 pub struct Fetch {
-    agent: Agent,
+    dirs: Rc<AppDirs>,
+    config: OnceCell<FetchConfig>,
+    agent: OnceCell<Agent>,
+    certificate_trust: OnceCell<Arc<CertificateTrust>>,
 }
 impl Fetch {
-    const FETCH_TIMEOUT: u64 = 5; // Seconds
+    pub fn new(dirs: &Rc<AppDirs>) -> Self {
+        Self {
+            dirs: dirs.clone(),
+            config: OnceCell::new(),
+            agent: OnceCell::new(),
+            certificate_trust: OnceCell::new(),
+        }
+    }
+
+    /// Needs the config dirs
+    pub fn init(&self) {
+        let config = FetchConfig::load(&self.dirs);
+        let certificate_trust = Arc::new(CertificateTrust::load(&self.dirs));
+        let _ = self
+            .agent
+            .set(config.build_agent(&self.dirs, &certificate_trust));
+        let _ = self.config.set(config);
+        let _ = self.certificate_trust.set(certificate_trust);
+    }
+
+    /// SHA-256 fingerprint of the certificate the user trusted for this host
+    pub fn get_trusted_certificate(&self, host: &str) -> Option<String> {
+        self.certificate_trust
+            .get()?
+            .get_trusted(Self::normalize_host(host))
+    }
+
+    /// SHA-256 fingerprint of the last certificate of this host that failed verification
+    pub fn get_rejected_certificate(&self, host: &str) -> Option<String> {
+        self.certificate_trust
+            .get()?
+            .get_rejected(Self::normalize_host(host))
+    }
 
-    pub fn new() -> Self {
-        let agent: Agent = Agent::config_builder()
-            .timeout_global(Some(Duration::from_secs(Self::FETCH_TIMEOUT)))
-            .user_agent("Wget/1.21.3")
-            .build()
-            .into();
+    /// Only call after the user confirmed the fingerprint
+    pub fn trust_certificate(&self, host: &str, fingerprint: &str) -> Result<()> {
+        self.certificate_trust.get().unwrap().trust(
+            &self.dirs,
+            Self::normalize_host(host),
+            fingerprint,
+        )
+    }
 
-        Self { agent }
+    pub fn remove_trusted_certificate(&self, host: &str) -> Result<()> {
+        self.certificate_trust
+            .get()
+            .unwrap()
+            .remove(&self.dirs, Self::normalize_host(host))
     }
 
-    pub async fn get_as_string(&self, url: &str) -> Result<String> {
+    /// With `force` fresh cached responses are revalidated with the server as well
+    pub async fn get_as_string(
+        &self,
+        url: &str,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<String> {
         debug!("Fetching text from url: {url}");
-        let agent_clone = self.agent.clone();
-        let url = url.to_string();
-        let url_clone = url.clone();
+        let (bytes, _) = self.get_cached(url, force, cancellable).await?;
 
-        match gio::spawn_blocking(move || -> Result<String> {
-            let response = agent_clone
-                .get(url_clone)
-                .call()?
-                .body_mut()
-                .read_to_string()?;
-            Ok(response)
-        })
-        .await
-        {
-            Ok(Ok(text)) => Ok(text),
-            Ok(Err(error)) => Self::error_handler(&url, &error),
-            Err(error) => Self::error_handler(&url, &error),
+        Ok(String::from_utf8_lossy(&bytes).to_string())
+    }
+
+    /// Text with the url it was served from after redirects
+    pub async fn get_page(
+        &self,
+        url: &str,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<(String, Url)> {
+        debug!("Fetching page from url: {url}");
+        let (bytes, final_url) = self.get_cached(url, force, cancellable).await?;
+        let final_url = Url::parse(&final_url)?;
+        if final_url.as_str() != url {
+            debug!(
+                url,
+                final_url = final_url.to_string(),
+                "Page was redirected"
+            );
         }
+
+        Ok((String::from_utf8_lossy(&bytes).to_string(), final_url))
     }
 
-    pub async fn get_as_bytes(&self, url: &str) -> Result<Vec<u8>> {
+    pub async fn get_as_bytes(
+        &self,
+        url: &str,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<Vec<u8>> {
         debug!("Fetching bytes from url: {url}");
-        let agent_clone = self.agent.clone();
+        let (bytes, _) = self.get_cached(url, force, cancellable).await?;
+
+        Ok(bytes)
+    }
+
+    /// Revalidates cached responses with the server and falls back on them when offline.
+    /// Returns the body and the final url. Cancelling stops the request in the background
+    /// before the next attempt or before the body is downloaded.
+    async fn get_cached(
+        &self,
+        url: &str,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<(Vec<u8>, String)> {
+        let agent_clone = self.agent.get().unwrap().clone();
+        let config_clone = self.config.get().unwrap().clone();
+        let cache = FetchCache::new(&self.dirs.fetch_cache());
         let url = url.to_string();
         let url_clone = url.clone();
+        let cancellable_clone = cancellable.clone();
 
-        match gio::spawn_blocking(move || -> Result<Vec<u8>> {
-            let response = agent_clone
-                .get(url_clone)
-                .call()?
-                .body_mut()
-                .read_to_vec()?;
-            Ok(response)
+        match gio::spawn_blocking(move || {
+            Self::get_cached_blocking(
+                &agent_clone,
+                &config_clone,
+                &cache,
+                &url_clone,
+                force,
+                &cancellable_clone,
+            )
         })
         .await
         {
-            Ok(Ok(bytes)) => Ok(bytes),
+            Ok(Ok(response)) => Ok(response),
             Ok(Err(error)) => Self::error_handler(&url, &error),
             Err(error) => Self::error_handler(&url, &error),
         }
     }
 
+    fn get_cached_blocking(
+        agent: &Agent,
+        config: &FetchConfig,
+        cache: &FetchCache,
+        url: &str,
+        force: bool,
+        cancellable: &Cancellable,
+    ) -> Result<(Vec<u8>, String)> {
+        let cached = cache.get(url);
+        let get_cached_final_url =
+            |entry: &CacheEntry| entry.final_url.clone().unwrap_or_else(|| entry.url.clone());
+
+        if !force
+            && let Some((entry, body)) = &cached
+            && entry.is_fresh(config.get_cache_fresh_for())
+        {
+            debug!(url, "Using cached response");
+            return Ok((body.clone(), get_cached_final_url(entry)));
+        }
+
+        let mut response =
+            match Self::call_with_retries(agent, config, url, cached.as_ref(), cancellable) {
+                Ok(response) => response,
+                // The server answered, so the cache is not used for errors
+                Err(error @ ureq::Error::StatusCode(_)) => return Err(error.into()),
+                Err(_) if cancellable.is_cancelled() => bail!("Fetch cancelled"),
+                Err(error) => {
+                    let Some((entry, body)) = cached else {
+                        return Err(error.into());
+                    };
+                    info!(
+                        url,
+                        error = error.to_string(),
+                        "Offline, using cached response"
+                    );
+                    return Ok((body, get_cached_final_url(&entry)));
+                }
+            };
+        let final_url = response.get_uri().to_string();
+        let get_header = |name: header::HeaderName| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let cache_control = CacheControl::parse(get_header(header::CACHE_CONTROL).as_deref());
+
+        if response.status() == StatusCode::NOT_MODIFIED
+            && let Some((mut entry, body)) = cached
+        {
+            debug!(url, "Cached response not modified");
+            entry.touch();
+            entry.final_url = Some(final_url.clone());
+            entry.max_age = cache_control.max_age;
+            if let Err(error) = cache.set(&entry, None) {
+                error!("{error:?}");
+            }
+            return Ok((body, final_url));
+        }
+
+        // Checked before the body is downloaded
+        let content_type = response.headers().get(header::CONTENT_TYPE);
+        if !FetchConfig::is_allowed_content_type(content_type) {
+            bail!("Unsupported content type: {content_type:?}")
+        }
+
+        let entry = CacheEntry::new(
+            url,
+            get_header(header::CONTENT_TYPE),
+            get_header(header::ETAG),
+            get_header(header::LAST_MODIFIED),
+            Some(final_url.clone()),
+            cache_control.max_age,
+        );
+        if cancellable.is_cancelled() {
+            bail!("Fetch cancelled")
+        }
+        let body = response
+            .body_mut()
+            .with_config()
+            .limit(config.max_body_size)
+            .read_to_vec()?;
+
+        if cache_control.no_store {
+            debug!(url, "Response may not be stored");
+            cache.remove(url);
+        } else if let Err(error) = cache.set(&entry, Some(&body)) {
+            error!("{error:?}");
+        } else {
+            cache.evict(config.max_cache_size);
+        }
+
+        Ok((body, final_url))
+    }
+
+    /// Conditional request when there is a cached response
+    fn call_with_retries(
+        agent: &Agent,
+        config: &FetchConfig,
+        url: &str,
+        cached: Option<&(CacheEntry, Vec<u8>)>,
+        cancellable: &Cancellable,
+    ) -> Result<Response<Body>, ureq::Error> {
+        let mut attempt = 0;
+
+        loop {
+            if cancellable.is_cancelled() {
+                return Err(ureq::Error::Io(io::Error::new(
+                    io::ErrorKind::Interrupted,
+                    "Fetch cancelled",
+                )));
+            }
+
+            let mut request = agent.get(url);
+            if let Some((entry, _)) = cached {
+                if let Some(etag) = &entry.etag {
+                    request = request.header(header::IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &entry.last_modified {
+                    request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let result = request.call();
+            let should_retry = match &result {
+                Ok(_) | Err(ureq::Error::InvalidProxyUrl) => false,
+                Err(ureq::Error::StatusCode(status)) => {
+                    *status == StatusCode::TOO_MANY_REQUESTS.as_u16() || *status >= 500
+                }
+                Err(_) => true,
+            };
+            if !should_retry || attempt >= config.retries {
+                return result;
+            }
+
+            let delay = config.get_retry_delay(attempt);
+            debug!(url, attempt, ?delay, "Retrying fetch");
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    /// Url hosts have brackets around IPv6 addresses, certificates don't
+    fn normalize_host(host: &str) -> &str {
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+
     // Any error logged and a anyhow::Error
     fn error_handler<R>(url: &str, error: impl std::fmt::Debug) -> Result<R> {
         let message = format!("Fetching '{url}' failed: '{error:?}'");
@@ -0,0 +1,200 @@
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::{debug, error};
+
+/// The directives of a `Cache-Control` header the cache uses
+#[derive(Debug, Default)]
+pub struct CacheControl {
+    pub no_store: bool,
+    /// Seconds, `no-cache` is a max age of 0 as it must be revalidated every time
+    pub max_age: Option<u64>,
+}
+impl CacheControl {
+    pub fn parse(header: Option<&str>) -> Self {
+        let mut cache_control = Self::default();
+
+        for directive in header.unwrap_or_default().split(',') {
+            let directive = directive.trim().to_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", seconds)) => {
+                    cache_control.max_age = cache_control
+                        .max_age
+                        .or_else(|| seconds.trim_matches('"').parse().ok());
+                }
+                None if directive == "no-cache" => cache_control.max_age = Some(0),
+                None if directive == "no-store" => cache_control.no_store = true,
+                _ => {}
+            }
+        }
+
+        cache_control
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Url after redirects, entries from older versions don't have it
+    #[serde(default)]
+    pub final_url: Option<String>,
+    /// Unix seconds of the last response, including revalidations
+    pub fetched_at: u64,
+    /// Seconds from `Cache-Control`, without it the configured default is used
+    #[serde(default)]
+    pub max_age: Option<u64>,
+}
+impl CacheEntry {
+    pub fn new(
+        url: &str,
+        content_type: Option<String>,
+        etag: Option<String>,
+        last_modified: Option<String>,
+        final_url: Option<String>,
+        max_age: Option<u64>,
+    ) -> Self {
+        Self {
+            url: url.to_string(),
+            content_type,
+            etag,
+            last_modified,
+            final_url,
+            fetched_at: now(),
+            max_age,
+        }
+    }
+
+    pub fn is_fresh(&self, default_fresh_for: Duration) -> bool {
+        let fresh_for = self.max_age.unwrap_or(default_fresh_for.as_secs());
+        now().saturating_sub(self.fetched_at) < fresh_for
+    }
+
+    pub fn touch(&mut self) {
+        self.fetched_at = now();
+    }
+}
+
+/// Responses on disk keyed by url, a json file with the headers next to the body
+#[derive(Clone)]
+pub struct FetchCache {
+    dir: PathBuf,
+}
+impl FetchCache {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub fn get(&self, url: &str) -> Option<(CacheEntry, Vec<u8>)> {
+        let (entry_path, body_path) = self.get_paths(url);
+
+        let entry = fs::read_to_string(&entry_path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<CacheEntry>(&json).ok())
+            // Hash collision
+            .filter(|entry| entry.url == url)?;
+        let body = fs::read(&body_path).ok()?;
+
+        Some((entry, body))
+    }
+
+    pub fn set(&self, entry: &CacheEntry, body: Option<&[u8]>) -> Result<()> {
+        let (entry_path, body_path) = self.get_paths(&entry.url);
+        debug!(url = entry.url, "Caching response");
+
+        if let Some(body) = body {
+            fs::write(&body_path, body)
+                .context(format!("Failed to write cache: {}", body_path.display()))?;
+        }
+        fs::write(&entry_path, serde_json::to_string(entry)?)
+            .context(format!("Failed to write cache: {}", entry_path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn remove(&self, url: &str) {
+        let (entry_path, body_path) = self.get_paths(url);
+        let _ = fs::remove_file(entry_path);
+        let _ = fs::remove_file(body_path);
+    }
+
+    /// Removes the least recently used responses until the cache fits in `max_size` bytes
+    pub fn evict(&self, max_size: u64) {
+        let Ok(files) = utils::files::get_entries_in_dir(&self.dir) else {
+            return;
+        };
+
+        // Per key the total size and the time of the last write of the entry
+        let mut keys = HashMap::<String, (u64, Option<SystemTime>)>::new();
+        for file in files {
+            let path = file.path();
+            let (Some(key), Ok(metadata)) = (path.file_stem(), file.metadata()) else {
+                continue;
+            };
+            let (size, used) = keys.entry(key.to_string_lossy().to_string()).or_default();
+            *size += metadata.len();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                *used = metadata.modified().ok();
+            }
+        }
+
+        let mut total_size = keys.values().map(|(size, _)| size).sum::<u64>();
+        if total_size <= max_size {
+            return;
+        }
+        let mut keys = keys.into_iter().collect::<Vec<_>>();
+        keys.sort_by_key(|(_, (_, used))| *used);
+
+        for (key, (size, _)) in keys {
+            if total_size <= max_size {
+                break;
+            }
+            debug!(key, "Evicting cached response");
+            for extension in ["json", "body"] {
+                let path = self.dir.join(format!("{key}.{extension}"));
+                if let Err(error) = fs::remove_file(&path)
+                    && path.exists()
+                {
+                    error!(
+                        error = error.to_string(),
+                        path = path.display().to_string(),
+                        "Failed to evict cached response"
+                    );
+                }
+            }
+            total_size = total_size.saturating_sub(size);
+        }
+    }
+
+    fn get_paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let key = get_key(url);
+        (
+            self.dir.join(format!("{key}.json")),
+            self.dir.join(format!("{key}.body")),
+        )
+    }
+}
+
+fn get_key(url: &str) -> String {
+    format!("{:016x}", utils::hash::fnv1a(url.as_bytes()))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,187 @@
+use super::tls::{self, CertificateTrust, TlsConnector};
+use crate::app_dirs::AppDirs;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{debug, error};
+use ureq::{
+    Agent, Proxy,
+    config::Config,
+    http::HeaderValue,
+    unversioned::{
+        resolver::DefaultResolver,
+        transport::{ConnectProxyConnector, Connector, TcpConnector},
+    },
+};
+
+/// `fetch.yml` in the config dirs, missing values use the defaults
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FetchConfig {
+    /// Seconds to resolve and connect
+    pub connect_timeout: u64,
+    /// Seconds to wait for the response and again for the body
+    pub read_timeout: u64,
+    pub user_agent: String,
+    /// Defaults to the `ALL_PROXY`, `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` env vars
+    pub proxy: Option<String>,
+    /// Hosts that skip `proxy`, e.g. `localhost` or `.internal.lan`
+    pub no_proxy: Vec<String>,
+    /// Retries on connection errors, 429 and 5xx responses
+    pub retries: u32,
+    /// Milliseconds before the first retry, doubled on every next one
+    pub retry_backoff: u64,
+    /// Bytes, larger responses are aborted while downloading
+    pub max_body_size: u64,
+    /// Seconds a cached response is used without asking the server, when it sets no max age
+    pub cache_fresh_for: u64,
+    /// Bytes, the least recently used responses are removed from the cache above it
+    pub max_cache_size: u64,
+    /// Trust the certificate authorities of the system next to the built-in ones
+    pub use_system_certificates: bool,
+    /// PEM bundle with extra certificate authorities, relative to the user config dir
+    pub ca_file: Option<PathBuf>,
+}
+impl Default for FetchConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 5,
+            read_timeout: 10,
+            user_agent: "Wget/1.21.3".to_string(),
+            proxy: None,
+            no_proxy: Vec::new(),
+            retries: 2,
+            retry_backoff: 500,
+            max_body_size: 10 * 1024 * 1024,
+            cache_fresh_for: 24 * 60 * 60,
+            max_cache_size: 100 * 1024 * 1024,
+            use_system_certificates: true,
+            ca_file: None,
+        }
+    }
+}
+impl FetchConfig {
+    const FILE_NAME: &str = "fetch.yml";
+    /// Content types that can be a page, a manifest or an icon
+    const ALLOWED_CONTENT_TYPES: [&str; 7] = [
+        "text/",
+        "image/",
+        "application/json",
+        "application/manifest+json",
+        "application/xhtml+xml",
+        "application/xml",
+        "application/octet-stream",
+    ];
+
+    pub fn load(app_dirs: &AppDirs) -> Self {
+        let Some(path) = app_dirs.find_config_file(Path::new(Self::FILE_NAME)) else {
+            debug!("No fetch config found, using defaults");
+            return Self::default();
+        };
+
+        match Self::from_file(&path) {
+            Ok(config) => {
+                debug!(?config, "Using fetch config: '{}'", path.display());
+                config
+            }
+            Err(error) => {
+                error!("Invalid fetch config, using defaults. Error: '{error:?}'");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn build_agent(&self, app_dirs: &AppDirs, trust: &Arc<CertificateTrust>) -> Agent {
+        let connect_timeout = Some(Duration::from_secs(self.connect_timeout));
+        let read_timeout = Some(Duration::from_secs(self.read_timeout));
+
+        let mut config_builder = Config::builder()
+            .timeout_resolve(connect_timeout)
+            .timeout_connect(connect_timeout)
+            .timeout_recv_response(read_timeout)
+            .timeout_recv_body(read_timeout)
+            .user_agent(&self.user_agent);
+
+        if let Some(proxy) = &self.proxy {
+            match self.build_proxy(proxy) {
+                Ok(proxy) => config_builder = config_builder.proxy(Some(proxy)),
+                Err(error) => error!("Invalid proxy, using the env vars. Error: '{error:?}'"),
+            }
+        }
+
+        let config = config_builder.build();
+        let root_store = tls::build_root_store(self, app_dirs);
+
+        match TlsConnector::new(root_store, trust) {
+            Ok(tls_connector) => {
+                let connector =
+                    ().chain(ConnectProxyConnector::default())
+                        .chain(TcpConnector::default())
+                        .chain(tls_connector);
+                Agent::with_parts(config, connector, DefaultResolver::default())
+            }
+            Err(error) => {
+                error!("Failed to set up certificates, using the defaults. Error: '{error:?}'");
+                config.into()
+            }
+        }
+    }
+
+    /// Missing content types are allowed, servers often leave it out for icons
+    pub fn is_allowed_content_type(content_type: Option<&HeaderValue>) -> bool {
+        let Some(content_type) = content_type.and_then(|value| value.to_str().ok()) else {
+            return true;
+        };
+        let content_type = content_type.trim().to_lowercase();
+
+        Self::ALLOWED_CONTENT_TYPES
+            .iter()
+            .any(|allowed| content_type.starts_with(allowed))
+    }
+
+    pub fn get_retry_delay(&self, attempt: u32) -> Duration {
+        Duration::from_millis(
+            self.retry_backoff
+                .saturating_mul(2_u64.saturating_pow(attempt)),
+        )
+    }
+
+    pub fn get_cache_fresh_for(&self) -> Duration {
+        Duration::from_secs(self.cache_fresh_for)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let file_string =
+            fs::read_to_string(path).context(format!("Failed to read: {}", path.display()))?;
+        // An empty file is a valid config with defaults
+        if file_string.trim().is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_yaml::from_str(&file_string).context(format!("Failed to parse: {}", path.display()))
+    }
+
+    fn build_proxy(&self, proxy: &str) -> Result<Proxy> {
+        let parsed = Proxy::new(proxy)?;
+
+        let mut builder = Proxy::builder(parsed.protocol())
+            .host(parsed.host())
+            .port(parsed.port());
+        if let Some(username) = parsed.username() {
+            builder = builder.username(username);
+        }
+        if let Some(password) = parsed.password() {
+            builder = builder.password(password);
+        }
+        for host in &self.no_proxy {
+            builder = builder.no_proxy(host);
+        }
+
+        Ok(builder.build()?)
+    }
+}
@@ -0,0 +1,360 @@
+use super::config::FetchConfig;
+use crate::app_dirs::AppDirs;
+use anyhow::{Context, Result};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    StreamOwned,
+    client::{
+        WebPkiServerVerifier,
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    },
+    crypto::ring,
+    pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+};
+use tracing::{debug, error, info};
+use ureq::unversioned::transport::{
+    Buffers, ConnectionDetails, Connector, Either, LazyBuffers, NextTimeout, Transport,
+    TransportAdapter,
+};
+
+/// Common locations of the system trust store, the flatpak runtime uses the first
+const SYSTEM_CA_BUNDLES: [&str; 4] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/ca-bundle.pem",
+    "/etc/ssl/cert.pem",
+];
+const TRUSTED_CERTIFICATES_FILE: &str = "trusted-certificates.yml";
+
+/// Certificates the user trusted per host and the last ones that failed verification.
+/// Fingerprints are SHA-256 of the server certificate.
+#[derive(Debug, Default)]
+pub struct CertificateTrust {
+    trusted: RwLock<HashMap<String, String>>,
+    rejected: Mutex<HashMap<String, String>>,
+}
+impl CertificateTrust {
+    /// Merges `trusted-certificates.yml` of all config layers
+    pub fn load(app_dirs: &AppDirs) -> Self {
+        let mut trusted = HashMap::new();
+
+        for layer in app_dirs.config_layers() {
+            let path = layer.join(TRUSTED_CERTIFICATES_FILE);
+            if !path.is_file() {
+                continue;
+            }
+            match read_trusted_file(&path) {
+                Ok(hosts) => trusted.extend(hosts),
+                Err(error) => error!("{error:?}"),
+            }
+        }
+        debug!(hosts = ?trusted.keys(), "Loaded trusted certificates");
+
+        Self {
+            trusted: RwLock::new(trusted),
+            rejected: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_trusted(&self, host: &str) -> Option<String> {
+        self.trusted.read().ok()?.get(host).cloned()
+    }
+
+    pub fn get_rejected(&self, host: &str) -> Option<String> {
+        self.rejected.lock().ok()?.get(host).cloned()
+    }
+
+    /// Saved in the user config layer
+    pub fn trust(&self, app_dirs: &AppDirs, host: &str, fingerprint: &str) -> Result<()> {
+        info!(host, fingerprint, "Trusting certificate");
+
+        let path = app_dirs.config().join(TRUSTED_CERTIFICATES_FILE);
+        let mut hosts = read_trusted_file(&path).unwrap_or_default();
+        hosts.insert(host.to_string(), fingerprint.to_string());
+        write_trusted_file(&path, &hosts)?;
+
+        if let Ok(mut trusted) = self.trusted.write() {
+            trusted.insert(host.to_string(), fingerprint.to_string());
+        }
+        if let Ok(mut rejected) = self.rejected.lock() {
+            rejected.remove(host);
+        }
+
+        Ok(())
+    }
+
+    /// Only the user config layer can be changed
+    pub fn remove(&self, app_dirs: &AppDirs, host: &str) -> Result<()> {
+        info!(host, "Removing trusted certificate");
+
+        let path = app_dirs.config().join(TRUSTED_CERTIFICATES_FILE);
+        let mut hosts = read_trusted_file(&path).unwrap_or_default();
+        hosts.remove(host);
+        write_trusted_file(&path, &hosts)?;
+
+        // A system-wide layer can still trust the host
+        let reloaded = Self::load(app_dirs)
+            .trusted
+            .into_inner()
+            .unwrap_or_default();
+        if let Ok(mut trusted) = self.trusted.write() {
+            *trusted = reloaded;
+        }
+
+        Ok(())
+    }
+
+    fn set_rejected(&self, host: &str, fingerprint: Option<String>) {
+        let Ok(mut rejected) = self.rejected.lock() else {
+            return;
+        };
+        match fingerprint {
+            Some(fingerprint) => rejected.insert(host.to_string(), fingerprint),
+            None => rejected.remove(host),
+        };
+    }
+}
+
+/// Mozilla roots, the system trust store and the `ca_file` bundle
+pub fn build_root_store(config: &FetchConfig, app_dirs: &AppDirs) -> RootCertStore {
+    let mut root_store = RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+
+    let mut bundles = Vec::new();
+    if config.use_system_certificates {
+        let system_bundle = env::var_os("SSL_CERT_FILE")
+            .map(PathBuf::from)
+            .into_iter()
+            .chain(SYSTEM_CA_BUNDLES.iter().map(PathBuf::from))
+            .find(|path| path.is_file());
+        bundles.extend(system_bundle);
+    }
+    if let Some(ca_file) = &config.ca_file {
+        // Relative to the user config dir
+        bundles.push(app_dirs.config().join(ca_file));
+    }
+
+    for bundle in bundles {
+        match CertificateDer::pem_file_iter(&bundle) {
+            Ok(certificates) => {
+                let (added, ignored) =
+                    root_store.add_parsable_certificates(certificates.filter_map(Result::ok));
+                debug!(added, ignored, "Loaded CA bundle: '{}'", bundle.display());
+            }
+            Err(error) => error!("Failed to read CA bundle: '{}': {error}", bundle.display()),
+        }
+    }
+
+    root_store
+}
+
+/// Uppercase hex pairs, like browsers show them
+pub fn get_fingerprint(certificate: &[u8]) -> String {
+    Sha256::digest(certificate)
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn read_trusted_file(path: &Path) -> Result<BTreeMap<String, String>> {
+    if !path.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    let file_string =
+        fs::read_to_string(path).context(format!("Failed to read: {}", path.display()))?;
+    if file_string.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    serde_yaml::from_str(&file_string).context(format!("Failed to parse: {}", path.display()))
+}
+
+fn write_trusted_file(path: &Path, hosts: &BTreeMap<String, String>) -> Result<()> {
+    let yaml = serde_yaml::to_string(hosts)?;
+    fs::write(path, yaml).context(format!("Failed to write: {}", path.display()))
+}
+
+/// Verifies with the root store, or with the fingerprint for hosts the user trusted
+#[derive(Debug)]
+struct CertificateVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    trust: Arc<CertificateTrust>,
+}
+impl ServerCertVerifier for CertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let host = server_name.to_str();
+        let fingerprint = get_fingerprint(end_entity);
+
+        if self.trust.get_trusted(&host).as_ref() == Some(&fingerprint) {
+            debug!(%host, "Using trusted certificate");
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        let result = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        );
+        match &result {
+            Err(rustls::Error::InvalidCertificate(reason)) => {
+                info!(%host, ?reason, fingerprint, "Rejected certificate");
+                self.trust.set_rejected(&host, Some(fingerprint));
+            }
+            Ok(_) => self.trust.set_rejected(&host, None),
+            Err(_) => {}
+        }
+
+        result
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Replaces the ureq rustls connector to verify with [`CertificateVerifier`]
+pub struct TlsConnector {
+    config: Arc<ClientConfig>,
+}
+impl TlsConnector {
+    pub fn new(root_store: RootCertStore, trust: &Arc<CertificateTrust>) -> Result<Self> {
+        let provider = Arc::new(ring::default_provider());
+        let inner =
+            WebPkiServerVerifier::builder_with_provider(Arc::new(root_store), provider.clone())
+                .build()?;
+        let verifier = CertificateVerifier {
+            inner,
+            trust: trust.clone(),
+        };
+
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+}
+impl<In: Transport> Connector<In> for TlsConnector {
+    type Out = Either<In, TlsTransport>;
+
+    fn connect(
+        &self,
+        details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, ureq::Error> {
+        let Some(transport) = chained else {
+            return Err(ureq::Error::Tls("No connection to wrap in TLS"));
+        };
+        if !details.needs_tls() || transport.is_tls() {
+            return Ok(Some(Either::A(transport)));
+        }
+
+        let host = details
+            .uri
+            .host()
+            .ok_or(ureq::Error::Tls("No host for TLS"))?
+            .trim_start_matches('[')
+            .trim_end_matches(']');
+        let server_name = ServerName::try_from(host)
+            .map_err(|_| ureq::Error::Tls("Invalid host for TLS"))?
+            .to_owned();
+
+        let connection = ClientConnection::new(self.config.clone(), server_name)?;
+        let stream = StreamOwned {
+            conn: connection,
+            sock: TransportAdapter::new(transport.boxed()),
+        };
+        let buffers = LazyBuffers::new(
+            details.config.input_buffer_size(),
+            details.config.output_buffer_size(),
+        );
+
+        Ok(Some(Either::B(TlsTransport { buffers, stream })))
+    }
+}
+impl fmt::Debug for TlsConnector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConnector").finish()
+    }
+}
+
+pub struct TlsTransport {
+    buffers: LazyBuffers,
+    stream: StreamOwned<ClientConnection, TransportAdapter>,
+}
+impl Transport for TlsTransport {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        &mut self.buffers
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), ureq::Error> {
+        self.stream.get_mut().set_timeout(timeout);
+        let output = &self.buffers.output()[..amount];
+        self.stream.write_all(output)?;
+
+        Ok(())
+    }
+
+    fn await_input(&mut self, timeout: NextTimeout) -> Result<bool, ureq::Error> {
+        self.stream.get_mut().set_timeout(timeout);
+        let input = self.buffers.input_append_buf();
+        let amount = self.stream.read(input)?;
+        self.buffers.input_appended(amount);
+
+        Ok(amount > 0)
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.stream.get_mut().get_mut().is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        true
+    }
+}
+impl fmt::Debug for TlsTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsTransport").finish()
+    }
+}
@@ -9,5 +9,7 @@ pub mod browsers;
 pub mod config;
 pub mod desktop_file;
 pub mod fetch;
+pub mod manifest;
+pub mod monogram;
 pub mod url;
 pub mod utils;
@@ -0,0 +1,196 @@
+// Web App Manifest: https://www.w3.org/TR/appmanifest/
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Deserializer, de::DeserializeOwned};
+use serde_json::Value;
+use tracing::debug;
+use url::Url;
+
+/// Values of an unexpected type are left out, instead of failing the whole manifest
+#[derive(Deserialize)]
+struct ManifestJson {
+    #[serde(default, deserialize_with = "lenient")]
+    name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    short_name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    start_url: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    scope: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    description: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    theme_color: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    background_color: Option<String>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    shortcuts: Option<Vec<ShortcutJson>>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    icons: Option<Vec<IconJson>>,
+}
+#[derive(Deserialize)]
+struct ShortcutJson {
+    #[serde(default, deserialize_with = "lenient")]
+    name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    short_name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    description: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    url: Option<String>,
+}
+#[derive(Deserialize)]
+struct IconJson {
+    #[serde(default, deserialize_with = "lenient")]
+    src: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    sizes: Option<String>,
+    #[serde(rename = "type", default, deserialize_with = "lenient")]
+    mime_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    purpose: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestShortcut {
+    pub name: String,
+    pub short_name: Option<String>,
+    pub description: Option<String>,
+    pub url: Url,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManifestIcon {
+    pub url: Url,
+    pub sizes: Option<String>,
+    pub mime_type: Option<String>,
+    pub purpose: Option<String>,
+}
+
+/// The parts of a manifest that are used for web apps.
+/// Urls are absolute and invalid or out of scope values are left out.
+#[derive(Debug, Clone)]
+pub struct WebAppManifest {
+    pub url: Url,
+    pub name: Option<String>,
+    pub short_name: Option<String>,
+    pub start_url: Option<Url>,
+    pub scope: Option<Url>,
+    pub description: Option<String>,
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+    pub shortcuts: Vec<ManifestShortcut>,
+    pub icons: Vec<ManifestIcon>,
+}
+impl WebAppManifest {
+    /// `manifest_url` is where the manifest was fetched from,
+    /// `document_url` is the page that links to it.
+    pub fn parse(json: &str, manifest_url: &Url, document_url: &Url) -> Result<Self> {
+        let manifest_json = serde_json::from_str::<ManifestJson>(json)
+            .context(format!("Failed to parse manifest: '{manifest_url}'"))?;
+
+        let resolve =
+            |href: Option<String>| non_empty(href).and_then(|href| manifest_url.join(&href).ok());
+
+        // The start url must be on the same origin as the page
+        let start_url = resolve(manifest_json.start_url)
+            .filter(|start_url| start_url.origin() == document_url.origin());
+
+        // The start url must be within scope
+        let scope_base = start_url.as_ref().unwrap_or(document_url);
+        let scope = resolve(manifest_json.scope).filter(|scope| is_within_scope(scope_base, scope));
+
+        let shortcuts = manifest_json
+            .shortcuts
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|shortcut| {
+                let name = non_empty(shortcut.name)?;
+                let url = resolve(shortcut.url)?;
+                let is_in_scope = match &scope {
+                    Some(scope) => is_within_scope(&url, scope),
+                    None => url.origin() == scope_base.origin(),
+                };
+                if !is_in_scope {
+                    debug!(url = url.to_string(), "Manifest shortcut out of scope");
+                    return None;
+                }
+
+                Some(ManifestShortcut {
+                    name,
+                    short_name: non_empty(shortcut.short_name),
+                    description: non_empty(shortcut.description),
+                    url,
+                })
+            })
+            .collect();
+
+        let icons = manifest_json
+            .icons
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|icon| {
+                Some(ManifestIcon {
+                    url: resolve(icon.src)?,
+                    sizes: non_empty(icon.sizes),
+                    mime_type: non_empty(icon.mime_type),
+                    purpose: non_empty(icon.purpose),
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            url: manifest_url.clone(),
+            name: non_empty(manifest_json.name),
+            short_name: non_empty(manifest_json.short_name),
+            start_url,
+            scope,
+            description: non_empty(manifest_json.description),
+            theme_color: non_empty(manifest_json.theme_color),
+            background_color: non_empty(manifest_json.background_color),
+            shortcuts,
+            icons,
+        })
+    }
+
+    /// The full name, or the short name when there is none
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref().or(self.short_name.as_deref())
+    }
+}
+
+fn lenient<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).ok())
+}
+
+/// Like [`lenient`] for every item, so one bad item does not drop the list
+fn lenient_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let Value::Array(items) = Value::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    Ok(Some(
+        items
+            .into_iter()
+            .filter_map(|item| serde_json::from_value(item).ok())
+            .collect(),
+    ))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn is_within_scope(url: &Url, scope: &Url) -> bool {
+    url.origin() == scope.origin() && url.path().starts_with(scope.path())
+}
@@ -0,0 +1,52 @@
+use crate::utils;
+use url::Url;
+
+/// GNOME palette, the base and the darker bottom edge. Yellow is left out, white text
+/// is hard to read on it.
+const COLORS: [(&str, &str); 7] = [
+    ("#3584e4", "#1a5fb4"),
+    ("#33d17a", "#26a269"),
+    ("#ff7800", "#c64600"),
+    ("#e01b24", "#a51d2d"),
+    ("#9141ac", "#613583"),
+    ("#986a44", "#63452c"),
+    ("#5e5c64", "#3d3846"),
+];
+
+/// Svg of the first letter of the name, or of the domain when there is no name.
+/// The same text always gets the same color.
+pub fn build_monogram(name: Option<&str>, url: Option<&str>) -> Vec<u8> {
+    let domain = url
+        .and_then(|url| Url::parse(url).ok())
+        .and_then(|url| url.host_str().map(str::to_string))
+        .map(|host| host.trim_start_matches("www.").to_string());
+    let text = name
+        .map(str::trim)
+        .filter(|name| name.chars().any(char::is_alphanumeric))
+        .map(str::to_string)
+        .or(domain)
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let letter = text
+        .chars()
+        .find(|char| char.is_alphanumeric())
+        .map_or_else(|| "?".to_string(), |char| char.to_uppercase().to_string());
+    let (color, edge_color) = COLORS[get_color_index(&text)];
+
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="128" height="128" viewBox="0 0 128 128">
+  <rect x="12" y="16" width="104" height="100" rx="20" fill="{edge_color}"/>
+  <rect x="12" y="12" width="104" height="100" rx="20" fill="{color}"/>
+  <text x="64" y="62" font-family="Cantarell, sans-serif" font-size="60" font-weight="bold" fill="#ffffff" text-anchor="middle" dominant-baseline="central">{letter}</text>
+</svg>
+"##
+    )
+    .into_bytes()
+}
+
+fn get_color_index(text: &str) -> usize {
+    let hash = utils::hash::fnv1a(text.as_bytes());
+
+    usize::try_from(hash % COLORS.len() as u64).unwrap_or_default()
+}
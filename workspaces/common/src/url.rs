@@ -5,6 +5,8 @@ pub trait UrlExt {
     fn get_base_url(&self) -> Result<Url>;
     fn has_path(&self) -> bool;
     fn sanitize(&self) -> Url;
+    fn is_web_url(&self) -> bool;
+    fn get_domain(&self) -> Option<String>;
 }
 impl UrlExt for Url {
     /// Get a new `URL` that only contains the base part
@@ -38,4 +40,20 @@ impl UrlExt for Url {
 
         self_mut_clone
     }
+
+    /// An `http(s)` url with a host, a domain name, IP address or `localhost`
+    fn is_web_url(&self) -> bool {
+        matches!(self.scheme(), "http" | "https")
+            && self.host_str().is_some_and(|host| !host.is_empty())
+    }
+
+    /// Host with the port when it is not the default for the scheme,
+    /// e.g. `example.com`, `192.168.1.20:8123` or `[::1]:3000`
+    fn get_domain(&self) -> Option<String> {
+        let host = self.host_str()?;
+        match self.port() {
+            Some(port) => Some(format!("{host}:{port}")),
+            None => Some(host.to_string()),
+        }
+    }
 }
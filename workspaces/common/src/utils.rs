@@ -3,9 +3,9 @@ pub mod files {
     use std::{
         fs::{self, DirEntry},
         os,
-        path::Path,
+        path::{Path, PathBuf},
     };
-    use tracing::debug;
+    use tracing::{debug, error};
 
     pub fn get_entries_in_dir(dir: &Path) -> Result<Vec<DirEntry>> {
         fs::read_dir(dir)
@@ -15,6 +15,51 @@ pub mod files {
             .map_err(std::convert::Into::into)
     }
 
+    /// Errors when `path` does not resolve (following symlinks) to something inside one of `roots`.
+    /// A root itself is not allowed.
+    pub fn ensure_contained(path: &Path, roots: &[PathBuf]) -> Result<()> {
+        let resolved = path
+            .canonicalize()
+            .context(format!("Could not resolve path: {}", path.display()))?;
+
+        let is_contained = roots
+            .iter()
+            .filter_map(|root| root.canonicalize().ok())
+            .any(|root| resolved != root && resolved.starts_with(&root));
+
+        if !is_contained {
+            let roots = roots
+                .iter()
+                .map(|root| root.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            error!(
+                path = path.display().to_string(),
+                resolved = resolved.display().to_string(),
+                roots,
+                "Refused to remove path outside of allowed dirs"
+            );
+            bail!(
+                "Refused to remove '{}', it is outside of the allowed folders",
+                path.display()
+            )
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_file_contained(path: &Path, roots: &[PathBuf]) -> Result<()> {
+        ensure_contained(path, roots)?;
+        debug!(path = path.display().to_string(), "Removing file");
+        fs::remove_file(path).context(format!("Failed to remove file: {}", path.display()))
+    }
+
+    pub fn remove_dir_all_contained(path: &Path, roots: &[PathBuf]) -> Result<()> {
+        ensure_contained(path, roots)?;
+        debug!(path = path.display().to_string(), "Removing dir");
+        fs::remove_dir_all(path).context(format!("Failed to remove dir: {}", path.display()))
+    }
+
     pub fn create_symlink(symlink_path: &Path, target: &Path) -> Result<()> {
         let mut target = target.to_path_buf();
         let a = symlink_path.display().to_string();
@@ -71,7 +116,7 @@ pub mod env {
         std::env::var("WAH_LOG")
             .with_context(|| {
                 let info = "No LOG environment variable set";
-                println!("{info}");
+                eprintln!("{info}");
                 info
             })
             .and_then(|level_str| {
@@ -95,6 +140,8 @@ pub mod env {
 }
 
 pub mod strings {
+    use std::{cmp::Ordering, iter::Peekable};
+
     pub fn capitalize(string: &str) -> String {
         let mut chars = string.chars();
         chars
@@ -112,6 +159,55 @@ pub mod strings {
             .collect::<Vec<_>>()
             .join(" ")
     }
+
+    /// Case insensitive order where numbers are compared by value, so `app-1.10` comes after
+    /// `app-1.9`
+    pub fn compare_natural(a: &str, b: &str) -> Ordering {
+        let mut a_chars = a.chars().flat_map(char::to_lowercase).peekable();
+        let mut b_chars = b.chars().flat_map(char::to_lowercase).peekable();
+
+        loop {
+            let (Some(a_char), Some(b_char)) = (a_chars.peek().copied(), b_chars.peek().copied())
+            else {
+                return a_chars.count().cmp(&b_chars.count()).then_with(|| a.cmp(b));
+            };
+
+            let ordering = if a_char.is_ascii_digit() && b_char.is_ascii_digit() {
+                let a_number = take_digits(&mut a_chars);
+                let b_number = take_digits(&mut b_chars);
+                let a_number = a_number.trim_start_matches('0');
+                let b_number = b_number.trim_start_matches('0');
+                a_number
+                    .len()
+                    .cmp(&b_number.len())
+                    .then_with(|| a_number.cmp(b_number))
+            } else {
+                a_chars.next();
+                b_chars.next();
+                a_char.cmp(&b_char)
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+    }
+
+    fn take_digits(chars: &mut Peekable<impl Iterator<Item = char>>) -> String {
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+            digits.push(digit);
+        }
+        digits
+    }
+}
+
+pub mod hash {
+    /// FNV-1a, stable between builds unlike the std hasher
+    pub fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
 }
 
 pub mod log {
@@ -131,10 +227,13 @@ pub mod log {
 
 pub mod command {
     use crate::utils::env;
-    use anyhow::{Result, bail};
-    use gtk::glib;
-    use std::{fmt::Write, process::Command};
-    use tracing::debug;
+    use anyhow::{Context, Result, bail};
+    use std::{
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+        thread,
+    };
+    use tracing::{debug, error};
 
     pub struct Response {
         pub success: bool,
@@ -143,60 +242,151 @@ pub mod command {
         pub stderr: String,
     }
 
-    pub fn test_command_available_sync(command: &str) -> bool {
-        let run_command = format!("which {command}");
-        let run_command = run_command.trim();
+    /// A command that runs on the host, also from inside the flatpak sandbox.
+    /// Arguments are passed as is and never parsed by a shell.
+    pub struct HostCommand {
+        program: String,
+        args: Vec<String>,
+        envs: Vec<(String, String)>,
+        current_dir: Option<PathBuf>,
+    }
+    impl HostCommand {
+        pub fn new(program: &str) -> Self {
+            Self {
+                program: program.to_string(),
+                args: Vec::new(),
+                envs: Vec::new(),
+                current_dir: None,
+            }
+        }
 
-        let Ok(response) = run_command_sync(run_command) else {
-            return false;
-        };
+        /// First item is the program, the rest are arguments.
+        pub fn from_args(args: &[String]) -> Result<Self> {
+            let (program, args) = args.split_first().context("Empty command")?;
+            Ok(Self::new(program).args(args))
+        }
 
-        if !response.success {
-            return false;
+        #[must_use]
+        pub fn arg(mut self, arg: &str) -> Self {
+            self.args.push(arg.to_string());
+            self
         }
 
-        true
-    }
+        #[must_use]
+        pub fn args<S: AsRef<str>>(mut self, args: &[S]) -> Self {
+            self.args
+                .extend(args.iter().map(|arg| arg.as_ref().to_string()));
+            self
+        }
 
-    pub fn run_command_background(command: &str) -> Result<()> {
-        let mut run_command = String::new();
+        #[must_use]
+        pub fn env(mut self, key: &str, value: &str) -> Self {
+            self.envs.push((key.to_string(), value.to_string()));
+            self
+        }
 
-        if env::is_flatpak_container() {
-            write!(run_command, "flatpak-spawn --host")?;
+        #[must_use]
+        pub fn current_dir(mut self, dir: &Path) -> Self {
+            self.current_dir = Some(dir.to_path_buf());
+            self
         }
-        write!(run_command, " {command}")?;
-        let run_command = run_command.trim();
 
-        debug!(command = run_command, "Running background command");
-        glib::spawn_command_line_async(run_command).map_err(Into::into)
-    }
+        pub fn run_sync(&self) -> Result<Response> {
+            let mut command = self.to_command()?;
+            debug!(command = self.to_string(), "Running sync command");
 
-    pub fn run_command_sync(command: &str) -> Result<Response> {
-        let mut run_command = String::new();
+            let output = command
+                .stdin(Stdio::null())
+                .output()
+                .context(format!("Failed to run command: {self}"))?;
 
-        if env::is_flatpak_container() {
-            write!(run_command, "flatpak-spawn --host")?;
+            Ok(Response {
+                success: output.status.success(),
+                status: output.status.code().unwrap_or(999_999),
+                stdout: parse_output(&output.stdout),
+                stderr: parse_output(&output.stderr),
+            })
         }
-        write!(run_command, " {command}")?;
-        let run_command = run_command.trim();
 
-        let mut args = glib::shell_parse_argv(run_command)?;
-        if args.is_empty() {
-            bail!("Incorrect command")
+        pub fn run_background(&self) -> Result<()> {
+            let mut command = self.to_command()?;
+            debug!(command = self.to_string(), "Running background command");
+
+            let mut child = command
+                .stdin(Stdio::null())
+                .spawn()
+                .context(format!("Failed to run command: {self}"))?;
+
+            // Reap the child when it exits so it does not linger as a zombie
+            let command_string = self.to_string();
+            thread::spawn(move || {
+                if let Err(error) = child.wait() {
+                    error!(
+                        command = command_string,
+                        error = error.to_string(),
+                        "Failed to wait for background command"
+                    );
+                }
+            });
+
+            Ok(())
         }
-        let command = args.remove(0);
 
-        debug!(command = run_command, "Running sync command");
-        let output = Command::new(command).args(args).output()?;
+        fn to_command(&self) -> Result<Command> {
+            if self.program.is_empty() || self.program.starts_with('-') {
+                bail!("Invalid program: '{}'", self.program)
+            }
 
-        let response = Response {
-            success: output.status.success(),
-            status: output.status.code().unwrap_or(999_999),
-            stdout: parse_output(&output.stdout),
-            stderr: parse_output(&output.stderr),
-        };
+            if !env::is_flatpak_container() {
+                let mut command = Command::new(&self.program);
+                command.args(&self.args);
+                command.envs(self.envs.clone());
+                if let Some(current_dir) = &self.current_dir {
+                    command.current_dir(current_dir);
+                }
+                return Ok(command);
+            }
+
+            // Options for flatpak-spawn must come before the program
+            let mut command = Command::new("flatpak-spawn");
+            command.arg("--host");
+            for (key, value) in &self.envs {
+                command.arg(format!("--env={key}={value}"));
+            }
+            if let Some(current_dir) = &self.current_dir {
+                command.arg(format!("--directory={}", current_dir.display()));
+            }
+            command.arg(&self.program);
+            command.args(&self.args);
+
+            Ok(command)
+        }
+    }
+    impl std::fmt::Display for HostCommand {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{}", self.program)?;
+            for arg in &self.args {
+                write!(f, " {arg:?}")?;
+            }
+            Ok(())
+        }
+    }
+
+    pub fn test_command_available_sync(command: &str) -> bool {
+        HostCommand::new("which")
+            .arg(command)
+            .run_sync()
+            .is_ok_and(|response| response.success)
+    }
+
+    /// Only for fixed commands, use `HostCommand` for anything with user values.
+    pub fn run_command_sync(command: &str) -> Result<Response> {
+        let args = glib::shell_parse_argv(command)?
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect::<Vec<_>>();
 
-        Ok(response)
+        HostCommand::from_args(&args)?.run_sync()
     }
 
     pub fn parse_output(std_descriptor: &[u8]) -> String {
@@ -59,6 +59,7 @@ fn main() -> Result<()> {
     update_submodules()?;
     create_app_desktop_file()?;
     create_app_icon()?;
+    update_config_hashes()?;
 
     let (releases_xml, new_version) = generate_changelog()?;
     update_cargo_with_new_version(&new_version)?;
@@ -176,6 +177,26 @@ fn create_app_icon() -> Result<()> {
     Ok(())
 }
 
+fn update_config_hashes() -> Result<()> {
+    info!("==== Updating config hashes");
+
+    let save_path = assets_path().join("config-hashes.txt");
+    fs::write(&save_path, assets::get_config_hashes_with_current()).inspect_err(|err| {
+        error!(
+            error = err.to_string(),
+            path = &save_path.to_string_lossy().to_string(),
+            "Failed to save config hashes"
+        );
+    })?;
+
+    info!(
+        config_hashes = &save_path.to_string_lossy().to_string(),
+        "Updated config hashes:"
+    );
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_lines)] // No exports of types from git_cliff...
 fn generate_changelog() -> Result<(String, Version)> {
     info!("==== Generating changelogs");